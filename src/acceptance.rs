@@ -0,0 +1,291 @@
+use crate::cli::Config;
+
+use ordered_float::OrderedFloat;
+use std::{collections::HashSet, fmt::Debug, fmt::Display, str::FromStr};
+
+/// Decides whether the attacker trusts the result of a dependent linkage attack. Accepted target traces
+/// are linked to a user and extend the history of that user in subsequent attacks.
+pub trait AcceptancePolicy: Debug {
+    /// Returns the user to which the target trace is linked, if any. The candidate tuples are sorted from
+    /// the best to the worst match and contain the best score of every candidate user once.
+    fn accept(&self, user_target: &u32, candidates: &[(u32, OrderedFloat<f64>)]) -> Option<u32>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AcceptanceRule {
+    #[default]
+    GapRatio,
+    ScoreThreshold,
+    ZScore,
+    Oracle,
+    Never,
+}
+
+impl FromStr for AcceptanceRule {
+    type Err = String;
+    fn from_str(s: &str) -> Result<AcceptanceRule, Self::Err> {
+        match s {
+            "gap_ratio" => Ok(AcceptanceRule::GapRatio),
+            "score_threshold" => Ok(AcceptanceRule::ScoreThreshold),
+            "z_score" => Ok(AcceptanceRule::ZScore),
+            "oracle" => Ok(AcceptanceRule::Oracle),
+            "never" => Ok(AcceptanceRule::Never),
+            x => Err(format!("The supplied acceptance rule does not exist: {:?}", x)),
+        }
+    }
+}
+
+impl Display for AcceptanceRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            AcceptanceRule::GapRatio => "gap_ratio",
+            AcceptanceRule::ScoreThreshold => "score_threshold",
+            AcceptanceRule::ZScore => "z_score",
+            AcceptanceRule::Oracle => "oracle",
+            AcceptanceRule::Never => "never",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl AcceptanceRule {
+    /// Returns the threshold used if none is configured, None if the rule requires one.
+    pub fn default_threshold(&self) -> Option<f64> {
+        match self {
+            AcceptanceRule::GapRatio => Some(0.75),
+            AcceptanceRule::ZScore => Some(2.0),
+            AcceptanceRule::ScoreThreshold => None,
+            AcceptanceRule::Oracle | AcceptanceRule::Never => Some(0.0),
+        }
+    }
+}
+
+/// Creates the acceptance policy selected in the configuration.
+pub fn from_config(config: &Config) -> Box<dyn AcceptancePolicy> {
+    let threshold = config
+        .acceptance_threshold
+        .or_else(|| config.acceptance.default_threshold())
+        .unwrap();
+    let higher_is_better = config.approach.higher_is_better();
+    match config.acceptance {
+        AcceptanceRule::GapRatio => Box::new(GapRatioPolicy { threshold }),
+        AcceptanceRule::ScoreThreshold => Box::new(ScoreThresholdPolicy {
+            threshold,
+            higher_is_better,
+        }),
+        AcceptanceRule::ZScore => Box::new(ZScorePolicy {
+            threshold,
+            higher_is_better,
+        }),
+        AcceptanceRule::Oracle => Box::new(OraclePolicy),
+        AcceptanceRule::Never => Box::new(NeverPolicy),
+    }
+}
+
+/// Reduces the sorted tuples of all compared traces to the best tuple of every user.
+pub fn best_per_user(tuples: &[(u32, OrderedFloat<f64>)]) -> Vec<(u32, OrderedFloat<f64>)> {
+    let mut seen_users: HashSet<u32> = HashSet::with_capacity(tuples.len());
+    tuples
+        .iter()
+        .filter(|(user, _)| seen_users.insert(*user))
+        .cloned()
+        .collect()
+}
+
+/// Accepts the best candidate if the gap to the second is large compared to the gap between the second
+/// and the third, i.e. if `1 - gap(2nd, 3rd) / gap(1st, 2nd)` reaches the threshold. Works for scores
+/// and distances alike. With only two candidates, any gap is large enough; a single candidate is never accepted.
+#[derive(Debug)]
+pub struct GapRatioPolicy {
+    pub threshold: f64,
+}
+
+impl AcceptancePolicy for GapRatioPolicy {
+    fn accept(&self, _user_target: &u32, candidates: &[(u32, OrderedFloat<f64>)]) -> Option<u32> {
+        let (first, second) = match candidates {
+            [first, second, ..] => (first, second),
+            _ => return None,
+        };
+        let gap_1st_to_2nd = (first.1.into_inner() - second.1.into_inner()).abs();
+        let gap_2nd_to_3rd = candidates
+            .get(2)
+            .map(|third| (second.1.into_inner() - third.1.into_inner()).abs())
+            .unwrap_or_default();
+        if gap_1st_to_2nd == 0.0 {
+            return None;
+        }
+        let significance_level = 1.0 - gap_2nd_to_3rd / gap_1st_to_2nd;
+        (significance_level >= self.threshold).then_some(first.0)
+    }
+}
+
+/// Accepts the best candidate if its score reaches the threshold, or its distance does not exceed it.
+#[derive(Debug)]
+pub struct ScoreThresholdPolicy {
+    pub threshold: f64,
+    pub higher_is_better: bool,
+}
+
+impl AcceptancePolicy for ScoreThresholdPolicy {
+    fn accept(&self, _user_target: &u32, candidates: &[(u32, OrderedFloat<f64>)]) -> Option<u32> {
+        let (user, score) = candidates.first()?;
+        let passes = if self.higher_is_better {
+            score.into_inner() >= self.threshold
+        } else {
+            score.into_inner() <= self.threshold
+        };
+        passes.then_some(*user)
+    }
+}
+
+/// Accepts the best candidate if it stands out from the distribution of all candidates, i.e. if its
+/// z-score reaches the threshold. Distances are negated, such that smaller distances stand out positively.
+#[derive(Debug)]
+pub struct ZScorePolicy {
+    pub threshold: f64,
+    pub higher_is_better: bool,
+}
+
+impl AcceptancePolicy for ZScorePolicy {
+    fn accept(&self, _user_target: &u32, candidates: &[(u32, OrderedFloat<f64>)]) -> Option<u32> {
+        let (user, best_score) = candidates.first()?;
+        let sign = if self.higher_is_better { 1.0 } else { -1.0 };
+        let scores: Vec<f64> = candidates.iter().map(|(_, score)| sign * score.into_inner()).collect();
+        let mean = scores.iter().sum::<f64>() / scores.len() as f64;
+        let std = (scores.iter().map(|score| (score - mean).powi(2)).sum::<f64>()
+            / scores.len() as f64)
+            .sqrt();
+        if std == 0.0 {
+            return None;
+        }
+        let z_score = (sign * best_score.into_inner() - mean) / std;
+        (z_score >= self.threshold).then_some(*user)
+    }
+}
+
+/// Always links the target trace to the true user, as long as the true user is among the candidates.
+/// Serves as an upper bound for the benefit of dependent linkage attacks.
+#[derive(Debug)]
+pub struct OraclePolicy;
+
+impl AcceptancePolicy for OraclePolicy {
+    fn accept(&self, user_target: &u32, candidates: &[(u32, OrderedFloat<f64>)]) -> Option<u32> {
+        candidates
+            .iter()
+            .any(|(user, _)| user == user_target)
+            .then_some(*user_target)
+    }
+}
+
+/// Never links a target trace, such that the dependent linkage attacks behave like independent ones.
+#[derive(Debug)]
+pub struct NeverPolicy;
+
+impl AcceptancePolicy for NeverPolicy {
+    fn accept(&self, _user_target: &u32, _candidates: &[(u32, OrderedFloat<f64>)]) -> Option<u32> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidates(scores: &[f64]) -> Vec<(u32, OrderedFloat<f64>)> {
+        scores
+            .iter()
+            .enumerate()
+            .map(|(user, score)| (user as u32 + 1, OrderedFloat(*score)))
+            .collect()
+    }
+
+    #[test]
+    fn gap_ratio_depends_on_the_number_of_candidates() {
+        let policy = GapRatioPolicy { threshold: 0.75 };
+        assert_eq!(policy.accept(&1, &[]), None);
+        assert_eq!(policy.accept(&1, &candidates(&[10.0])), None);
+        // Without a third candidate, any gap is large enough
+        assert_eq!(policy.accept(&1, &candidates(&[10.0, 9.9])), Some(1));
+        // 1 - 1 / 4 reaches the threshold, 1 - 3 / 4 does not
+        assert_eq!(policy.accept(&1, &candidates(&[10.0, 6.0, 5.0, 0.0])), Some(1));
+        assert_eq!(policy.accept(&1, &candidates(&[10.0, 6.0, 3.0])), None);
+        // Distances work alike
+        assert_eq!(policy.accept(&1, &candidates(&[1.0, 5.0, 6.0])), Some(1));
+    }
+
+    #[test]
+    fn gap_ratio_rejects_ties_at_the_top() {
+        let policy = GapRatioPolicy { threshold: 0.0 };
+        assert_eq!(policy.accept(&1, &candidates(&[10.0, 10.0])), None);
+        assert_eq!(policy.accept(&1, &candidates(&[10.0, 10.0, 3.0])), None);
+    }
+
+    #[test]
+    fn score_threshold_follows_the_score_direction() {
+        let scores = ScoreThresholdPolicy {
+            threshold: 0.5,
+            higher_is_better: true,
+        };
+        assert_eq!(scores.accept(&2, &candidates(&[0.8, 0.1])), Some(1));
+        assert_eq!(scores.accept(&2, &candidates(&[0.5])), Some(1));
+        assert_eq!(scores.accept(&2, &candidates(&[0.4])), None);
+        assert_eq!(scores.accept(&2, &[]), None);
+
+        let distances = ScoreThresholdPolicy {
+            threshold: 0.5,
+            higher_is_better: false,
+        };
+        assert_eq!(distances.accept(&2, &candidates(&[0.3, 0.9])), Some(1));
+        assert_eq!(distances.accept(&2, &candidates(&[0.5])), Some(1));
+        assert_eq!(distances.accept(&2, &candidates(&[0.6])), None);
+    }
+
+    #[test]
+    fn z_score_follows_the_score_direction() {
+        // The best candidate is sqrt(3) standard deviations away from the mean of all candidates
+        for (higher_is_better, scores) in [(true, [4.0, 0.0, 0.0, 0.0]), (false, [0.0, 4.0, 4.0, 4.0])] {
+            let accepting = ZScorePolicy {
+                threshold: 1.7,
+                higher_is_better,
+            };
+            assert_eq!(accepting.accept(&3, &candidates(&scores)), Some(1));
+            let rejecting = ZScorePolicy {
+                threshold: 1.8,
+                higher_is_better,
+            };
+            assert_eq!(rejecting.accept(&3, &candidates(&scores)), None);
+        }
+    }
+
+    #[test]
+    fn z_score_rejects_candidates_without_spread() {
+        let policy = ZScorePolicy {
+            threshold: -10.0,
+            higher_is_better: true,
+        };
+        assert_eq!(policy.accept(&1, &candidates(&[2.0, 2.0, 2.0])), None);
+        assert_eq!(policy.accept(&1, &candidates(&[2.0])), None);
+        assert_eq!(policy.accept(&1, &[]), None);
+    }
+
+    #[test]
+    fn oracle_links_only_to_the_target_user() {
+        let candidates = candidates(&[0.9, 0.8, 0.7]);
+        assert_eq!(OraclePolicy.accept(&2, &candidates), Some(2));
+        assert_eq!(OraclePolicy.accept(&7, &candidates), None);
+        assert_eq!(NeverPolicy.accept(&1, &candidates), None);
+    }
+
+    #[test]
+    fn best_per_user_keeps_the_first_tuple_of_every_user() {
+        let tuples: Vec<(u32, OrderedFloat<f64>)> = [(1, 0.9), (2, 0.8), (1, 0.7), (3, 0.6), (2, 0.5)]
+            .iter()
+            .map(|(user, score)| (*user, OrderedFloat(*score)))
+            .collect();
+        assert_eq!(
+            best_per_user(&tuples),
+            vec![(1, OrderedFloat(0.9)), (2, OrderedFloat(0.8)), (3, OrderedFloat(0.6))]
+        );
+        assert!(best_per_user(&[]).is_empty());
+    }
+}
@@ -1,42 +1,170 @@
-use crate::parse::DataFields;
+use crate::acceptance::AcceptanceRule;
+use crate::frequency::{metrics::DistanceMetric, weighting::Weighting};
+use crate::parse::{self, DataFields};
+use crate::schema::{Column, Schema};
+use crate::sequence::alignment::{AlignmentScope, AlignmentStrategy, ScoringMatrix, Substitution};
+use crate::session::SessionStrategy;
+use crate::split::{self, SplitStrategy};
+use crate::utils;
 
-use std::str::FromStr;
+use std::{collections::HashSet, error::Error, fmt::Display, path::Path, str::FromStr};
 
 #[derive(Debug)]
 pub struct Config {
+    pub session_strategy: SessionStrategy,
     pub delay_limit: f64,
+    pub window_size: usize,
+    pub window_overlap: usize,
     pub fields: Vec<DataFields>,
+    pub field_weights: Vec<f64>,
+    pub learn_field_weights: bool,
     pub max_trace_len: usize,
     pub min_trace_len: usize,
     pub max_trace_duration: f64,
-    pub max_rate: f64,
     pub min_num_traces: usize,
     pub user_sample_size: usize,
     pub trace_sample_size: usize,
     pub target_trace_sample_size: usize,
-    pub metric: String,
+    pub split_strategy: SplitStrategy,
+    pub split_ratio: f64,
+    pub split_cutoff: Option<f64>,
+    pub num_folds: usize,
+    pub gap_buckets: Vec<f64>,
+    pub top_k: Vec<usize>,
+    pub metric: DistanceMetric,
+    pub field_metrics: Vec<DistanceMetric>,
+    pub weighting: Weighting,
     pub path: String,
+    pub schema: Schema,
+    pub snapshot: bool,
+    pub snapshot_dir: String,
     pub path_to_map: String,
+    pub load_map: bool,
     pub seed: u64,
     pub typical: bool,
     pub dependent: bool,
     pub multiple: bool,
-    pub strategy: String,
-    pub scoring_matrix: Vec<isize>,
-    pub approach: String,
-    pub scope: String,
+    pub strategy: AlignmentStrategy,
+    pub scoring_matrix: ScoringMatrix,
+    pub gap_open: Option<f64>,
+    pub substitution: Substitution,
+    pub approach: Approach,
+    pub scope: AlignmentScope,
+    pub experiment: Option<String>,
+    pub attack_log: Option<String>,
+    pub attack_log_candidates: usize,
+    pub bootstrap_samples: usize,
+    pub confidence_level: f64,
+    pub compare: Option<Vec<String>>,
+    pub unseen_fraction: f64,
+    pub match_threshold: Option<f64>,
+    pub acceptance: AcceptanceRule,
+    pub acceptance_threshold: Option<f64>,
+    /// Index of the run within an experiment, which tells the outputs of the runs apart.
+    pub run: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Approach {
+    #[default]
+    Sequence,
+    Frequency,
+}
+
+impl Approach {
+    /// Returns true if higher scores indicate better matches, i.e. for alignment scores as opposed to distances.
+    pub fn higher_is_better(&self) -> bool {
+        matches!(self, Approach::Sequence)
+    }
+}
+
+impl FromStr for Approach {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Approach, Self::Err> {
+        match s {
+            "sequence" => Ok(Approach::Sequence),
+            "frequency" => Ok(Approach::Frequency),
+            x => Err(format!("The supplied approach does not exist: {:?}", x)),
+        }
+    }
+}
+
+impl Display for Approach {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Approach::Sequence => "sequence",
+            Approach::Frequency => "frequency",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Error of loading the configuration, either from clap itself (which includes `--help`) or
+/// listing every invalid or inconsistent option.
+#[derive(Debug)]
+pub enum ConfigError {
+    Cli(clap::Error),
+    Invalid(Vec<String>),
+}
+
+impl ConfigError {
+    /// Prints the error and exits the process, like `clap::Error::exit`.
+    pub fn exit(&self) -> ! {
+        match self {
+            ConfigError::Cli(e) => e.exit(),
+            ConfigError::Invalid(_) => {
+                eprintln!("{}", self);
+                std::process::exit(2)
+            }
+        }
+    }
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConfigError::Cli(e) => write!(f, "{}", e),
+            ConfigError::Invalid(problems) => {
+                write!(f, "Invalid configuration:")?;
+                for problem in problems.iter() {
+                    write!(f, "\n  - {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+impl From<clap::Error> for ConfigError {
+    fn from(e: clap::Error) -> Self {
+        ConfigError::Cli(e)
+    }
 }
 
 /// Loads the (optional) command line arguments and the corresponding values. 
-pub fn get_cli_config() -> Result<Config, clap::Error> {
-    let matches = clap::App::new("Tracking-Users-by-Browsing-Behavior")
+pub fn get_cli_config() -> Result<Config, ConfigError> {
+    get_config_from(std::env::args_os())
+}
+
+/// Loads the configuration from the given arguments, the first of which is the name of the program.
+///
+/// All values are parsed and the resulting configuration is validated before returning, such that
+/// every problem is reported at once rather than surfacing one by one during the evaluation.
+pub fn get_config_from<I, T>(args: I) -> Result<Config, ConfigError>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<std::ffi::OsString> + Clone,
+{
+    let mut app = clap::App::new("Tracking-Users-by-Browsing-Behavior")
         .version("1.0")
         .author("Felix John")
         .arg(
             clap::Arg::new("approach")
                 .long("approach")
                 .help("Sequence alignment-based or frequency-based approach.")
-                .possible_values(&["sequence", "frequency"])
+                .possible_values(["sequence", "frequency"])
                 .default_value("sequence"),
         )
         .arg(
@@ -44,23 +172,56 @@ pub fn get_cli_config() -> Result<Config, clap::Error> {
                 .long("scoring_matrix")
                 .allow_hyphen_values(true)
                 .help("The scoring matrix to use for the alignment approach: ['equal', 'align', 'insert', 'delete'].")
-                .multiple_values(true)
+                .number_of_values(4)
                 .default_values(&["1", "-1", "-1", "-1"])
         )
+        .arg(
+            clap::Arg::new("gap_open")
+                .long("gap_open")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .help("Additional score of opening a gap in the alignment approach, whose positions are scored with 'insert' and 'delete' (affine gaps). A gap before the first aligned target element is charged like any other.")
+        )
+        .arg(
+            clap::Arg::new("substitution")
+                .long("substitution")
+                .help("How aligned elements that are not equal are scored: exact, or partial credit for urls of the same domain or category and close hours.")
+                .possible_values(["exact", "partial"])
+                .default_value("exact"),
+        )
         .arg(
             clap::Arg::new("scope")
                 .long("scope")
                 .help("The scope of the alignment algorithm: local or global.")
-                .possible_values(&["local", "global"])
+                .possible_values(["local", "global"])
                 .default_value("global"),
         )
         .arg(
             clap::Arg::new("strategy")
                 .long("strategy")
                 .help("The alignment strategy to use.")
-                .possible_values(&["sw", "nw"])
+                .possible_values(["sw", "nw"])
                 .default_value("nw"),
         )
+        .arg(
+            clap::Arg::new("session_strategy")
+                .long("session_strategy")
+                .help("How the browsing history of a user is split into traces (sessions).")
+                .possible_values(["inactivity", "day", "week", "sliding_window", "domain_change"])
+                .default_value("inactivity"),
+        )
+        .arg(
+            clap::Arg::new("window_size")
+                .long("window_size")
+                .help("Number of events per trace for the sliding_window session strategy.")
+                .default_value("50"),
+        )
+        .arg(
+            clap::Arg::new("window_overlap")
+                .long("window_overlap")
+                .help("Number of events shared by consecutive traces for the sliding_window session strategy.")
+                .default_value("0"),
+        )
         .arg(
             clap::Arg::new("delay_limit")
                 .long("delay_limit")
@@ -70,11 +231,24 @@ pub fn get_cli_config() -> Result<Config, clap::Error> {
         .arg(
             clap::Arg::new("fields")
                 .long("fields")
-                .possible_values(&["url", "category", "domain", "hour", "day", "age", "gender", "rate"])
+                .possible_values(["url", "category", "domain", "hour", "day", "age", "gender"])
                 .help("Data fields to consider for the analysis.")
                 .multiple_values(true)
                 .default_values(&["category", "domain", "age", "gender", "url"])
         )
+        .arg(
+            clap::Arg::new("field_weights")
+                .long("field_weights")
+                .takes_value(true)
+                .multiple_values(true)
+                .help("Weights of the data fields when combining their scores, e.g. url=0.5 domain=0.3 hour=0.2. Fields without a weight are weighted with one.")
+        )
+        .arg(
+            clap::Arg::new("learn_field_weights")
+                .long("learn_field_weights")
+                .default_value("false")
+                .help("Set to true to learn the weights of the data fields on a validation split of the observed traces.")
+        )
         .arg(
             clap::Arg::new("max_trace_len")
                 .long("max_trace_len")
@@ -117,12 +291,65 @@ pub fn get_cli_config() -> Result<Config, clap::Error> {
                 .default_value("1")
                 .help("Number of target traces to sample per user"),
         )
+        .arg(
+            clap::Arg::new("split_strategy")
+                .long("split_strategy")
+                .help("How the history of a user is split into observed and target traces.")
+                .possible_values(["chronological", "cutoff", "random", "leave_one_out", "k_fold"])
+                .default_value("chronological"),
+        )
+        .arg(
+            clap::Arg::new("split_ratio")
+                .long("split_ratio")
+                .default_value("0.5")
+                .help("Share of observed traces for the chronological and random split strategies."),
+        )
+        .arg(
+            clap::Arg::new("split_cutoff")
+                .long("split_cutoff")
+                .takes_value(true)
+                .help("Timestamp before which traces are observed and after which they are targets for the cutoff split strategy."),
+        )
+        .arg(
+            clap::Arg::new("num_folds")
+                .long("num_folds")
+                .default_value("4")
+                .help("Number of folds for the k_fold split strategy, at most min_num_traces."),
+        )
+        .arg(
+            clap::Arg::new("gap_buckets")
+                .long("gap_buckets")
+                .multiple_values(true)
+                .default_values(&["1", "7", "30"])
+                .help("Edges (in days) of the buckets of the time gap between the latest observed and the target trace for which the accuracy is reported."),
+        )
+        .arg(
+            clap::Arg::new("top_k")
+                .long("top_k")
+                .multiple_values(true)
+                .default_values(&["1", "5", "10"])
+                .help("Ranks k for which the share of target users ranked within the top k is reported.")
+        )
         .arg(
             clap::Arg::new("metric")
                 .long("metric")
                 .default_value("kullbrack_leibler")
                 .help("Distance metric to compare a pair of traces.")
-                .possible_values(&["euclidean", "manhattan", "cosine", "non_intersection", "bhattacharyya", "kullbrack_leibler", "total_variation", "jeffries_matusita", "chi_quared"]),
+                .possible_values(["euclidean", "manhattan", "cosine", "non_intersection", "bhattacharyya", "kullbrack_leibler", "kullbrack_leibler_reverse", "symmetric_kullbrack_leibler", "total_variation", "jeffries_matusita", "chi_quared", "jensen_shannon", "hellinger", "canberra", "bray_curtis", "wasserstein"]),
+        )
+        .arg(
+            clap::Arg::new("field_metrics")
+                .long("field_metrics")
+                .takes_value(true)
+                .multiple_values(true)
+                .help("Distance metrics of individual data fields, e.g. url:cosine hour:total_variation. Fields without a metric are compared with --metric.")
+        )
+        .arg(
+            clap::Arg::new("weighting")
+                .long("weighting")
+                .default_value("raw")
+                .help("Weighting of the url, domain and category histograms before they are compared.")
+                .possible_values(["raw", "tf_idf", "bm25", "binary", "log"]),
         )
         .arg(
             clap::Arg::new("path")
@@ -130,11 +357,42 @@ pub fn get_cli_config() -> Result<Config, clap::Error> {
                 .default_value("data/browsing.csv")
                 .help("Path to the dataset.")
         )
+        .arg(
+            clap::Arg::new("schema")
+                .long("schema")
+                .takes_value(true)
+                .help("Path to a TOML file with a [columns] table mapping the columns to the headers of the dataset.")
+        )
+        .arg(
+            clap::Arg::new("columns")
+                .long("columns")
+                .takes_value(true)
+                .multiple_values(true)
+                .help("Column mappings of the form column=header, e.g. user_id=panelist. Takes precedence over --schema.")
+        )
+        .arg(
+            clap::Arg::new("snapshot")
+                .long("snapshot")
+                .default_value("true")
+                .help("Set to true if the parsed traces should be cached in and loaded from a snapshot.")
+        )
+        .arg(
+            clap::Arg::new("snapshot_dir")
+                .long("snapshot_dir")
+                .default_value("tmp/snapshots")
+                .help("Directory in which the snapshots of parsed traces are stored.")
+        )
         .arg(
             clap::Arg::new("path_to_map")
                 .long("path_to_map")
                 .default_value("data/user_to_target_idx_map.pkl")
-                .help("Path to the dataset.")
+                .help("Path to the file (.pkl or .json) in which the sampled users and traces are stored.")
+        )
+        .arg(
+            clap::Arg::new("load_map")
+                .long("load_map")
+                .default_value("false")
+                .help("Set to true if the sampled users and traces should be loaded from path_to_map instead.")
         )
         .arg(
             clap::Arg::new("seed")
@@ -160,110 +418,505 @@ pub fn get_cli_config() -> Result<Config, clap::Error> {
                 .default_value("true")
                 .help("Set true of the linkage attacks are dependent on each other.")
         )
-        .get_matches();
+        .arg(
+            clap::Arg::new("experiment")
+                .long("experiment")
+                .takes_value(true)
+                .help("Path to an experiment file (TOML or JSON) declaring base parameters and a grid of parameters to sweep over.")
+        )
+        .arg(
+            clap::Arg::new("attack_log")
+                .long("attack_log")
+                .takes_value(true)
+                .help("Path to a file (.csv, or .jsonl for JSON Lines) to which the result of every linkage attack is written.")
+        )
+        .arg(
+            clap::Arg::new("attack_log_candidates")
+                .long("attack_log_candidates")
+                .default_value("10")
+                .help("Number of best matching candidate users per linkage attack written to the attack log.")
+        )
+        .arg(
+            clap::Arg::new("bootstrap_samples")
+                .long("bootstrap_samples")
+                .default_value("1000")
+                .help("Number of bootstrap samples to compute the confidence intervals and the paired bootstrap test.")
+        )
+        .arg(
+            clap::Arg::new("confidence_level")
+                .long("confidence_level")
+                .default_value("0.95")
+                .help("Confidence level of the confidence intervals. One minus the level is the significance level of the comparisons.")
+        )
+        .arg(
+            clap::Arg::new("compare")
+                .long("compare")
+                .number_of_values(2)
+                .help("Paths to two attack logs of runs on the same sampled targets whose top-k accuracies are compared instead of running an evaluation.")
+        )
+        .arg(
+            clap::Arg::new("unseen_fraction")
+                .long("unseen_fraction")
+                .default_value("0.0")
+                .help("Fraction of target users whose observed traces are withheld from the candidates (open world).")
+        )
+        .arg(
+            clap::Arg::new("match_threshold")
+                .long("match_threshold")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .help("Minimum alignment score (sequence) or maximum distance (frequency) of the best candidate to answer with a match instead of no match.")
+        )
+        .arg(
+            clap::Arg::new("acceptance")
+                .long("acceptance")
+                .possible_values(["gap_ratio", "score_threshold", "z_score", "oracle", "never"])
+                .default_value("gap_ratio")
+                .help("Rule deciding whether a dependent linkage attack links the target trace to the best candidate.")
+        )
+        .arg(
+            clap::Arg::new("acceptance_threshold")
+                .long("acceptance_threshold")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .help("Threshold of the acceptance rule. Defaults to 0.75 for gap_ratio and 2.0 for z_score, required for score_threshold.")
+        );
+    let matches = app.try_get_matches_from_mut(args)?;
+
+    let mut values = Values {
+        matches: &matches,
+        problems: Vec::new(),
+    };
+
+    // The parameters of every run are declared in the experiment file, such that they are not silently ignored
+    if matches.is_present("experiment") {
+        let other_args: Vec<String> = app
+            .get_arguments()
+            .filter(|arg| {
+                arg.get_id() != "experiment" && matches.value_source(arg.get_id()) == Some(clap::ValueSource::CommandLine)
+            })
+            .map(|arg| format!("--{}", arg.get_id()))
+            .collect();
+        if !other_args.is_empty() {
+            values.problems.push(format!(
+                "The options {} cannot be combined with --experiment, declare them in the base of the experiment instead",
+                other_args.join(", ")
+            ));
+        }
+    }
+
+    let mut schema = match matches.value_of("schema") {
+        Some(path) => Schema::from_file(path).unwrap_or_else(|e| {
+            values.problems.push(format!("Error loading the column schema {:?}: {}", path, e));
+            Schema::default()
+        }),
+        None => Schema::default(),
+    };
+    let overrides: Vec<String> = values.list("columns");
+    if let Err(e) = schema.apply_overrides(&overrides) {
+        values.problems.push(format!("Error applying the column mappings: {}", e));
+    }
+
+    let scoring_matrix = ScoringMatrix::try_from(values.list::<isize>("scoring_matrix").as_slice())
+        .unwrap_or_else(|e| {
+            values.problems.push(e);
+            ScoringMatrix::default()
+        });
+
+    let fields: Vec<DataFields> = values.list("fields");
+    let assignments: Vec<String> = values.list("field_weights");
+    let field_weights = parse::parse_field_values(&fields, &assignments, 1.0).unwrap_or_else(|e| {
+        values.problems.push(format!("Error parsing the field weights: {}", e));
+        vec![1.0; fields.len()]
+    });
+    let metric: DistanceMetric = values.get("metric");
+    let assignments: Vec<String> = values.list("field_metrics");
+    let field_metrics = parse::parse_field_values(&fields, &assignments, metric).unwrap_or_else(|e| {
+        values.problems.push(format!("Error parsing the field metrics: {}", e));
+        vec![metric; fields.len()]
+    });
 
     let config = Config {
-        delay_limit: matches
-            .value_of("delay_limit")
-            .unwrap_or_default()
-            .parse::<f64>()
-            .unwrap(),
-        metric: matches
-            .value_of("metric")
-            .map(String::from)
-            .unwrap_or_default(),
-        max_trace_len: matches
-            .value_of("max_trace_len")
-            .unwrap_or_default()
-            .parse::<usize>()
-            .unwrap(),
-        fields: matches
-            .values_of_lossy("fields")
-            .unwrap_or_default()
-            .iter()
-            .map(|x| DataFields::from_str(x).unwrap())
-            .collect(),
-        trace_sample_size: matches
-            .value_of("trace_sample_size")
-            .unwrap_or_default()
-            .parse::<usize>()
-            .unwrap(),
-        user_sample_size: matches
-            .value_of("user_sample_size")
-            .unwrap_or_default()
-            .parse::<usize>()
-            .unwrap(),
-        target_trace_sample_size: matches
-            .value_of("target_trace_sample_size")
-            .unwrap_or_default()
-            .parse::<usize>()
-            .unwrap(),
-        max_rate: matches
-            .value_of("max_rate")
-            .unwrap_or_default()
-            .parse::<f64>()
-            .unwrap(),
-        max_trace_duration: matches
-            .value_of("max_trace_duration")
-            .unwrap_or_default()
-            .parse::<f64>()
-            .unwrap(),
-        min_trace_len: matches
-            .value_of("min_trace_len")
-            .unwrap_or_default()
-            .parse::<usize>()
-            .unwrap(),
-        min_num_traces: matches
-            .value_of("min_num_traces")
-            .unwrap_or_default()
-            .parse::<usize>()
-            .unwrap(),
-        path: matches
-            .value_of("path")
-            .map(String::from)
-            .unwrap_or_default(),
-        path_to_map: matches
-            .value_of("path_to_map")
-            .map(String::from)
-            .unwrap_or_default(),
-        seed: matches
-            .value_of("seed")
-            .unwrap_or_default()
-            .parse::<u64>()
-            .unwrap(),
-        typical: matches
-            .value_of("typical")
-            .unwrap_or_default()
-            .parse::<bool>()
-            .unwrap(),
-        multiple: matches
-            .value_of("multiple")
-            .unwrap_or_default()
-            .parse::<bool>()
-            .unwrap(),
-        dependent: matches
-            .value_of("dependent")
-            .unwrap_or_default()
-            .parse::<bool>()
-            .unwrap(),
-        strategy: matches
-            .value_of("strategy")
-            .map(String::from)
-            .unwrap_or_default(),
-        scoring_matrix: matches
-            .values_of_lossy("scoring_matrix")
-            .unwrap_or_default()
-            .iter()
-            .map(|x| isize::from_str(x).unwrap())
-            .collect(),
-        approach: matches
-            .value_of("approach")
-            .map(String::from)
-            .unwrap_or_default(),
-        scope: matches
-            .value_of("scope")
-            .map(String::from)
-            .unwrap_or_default(),
+        session_strategy: values.get("session_strategy"),
+        window_size: values.get("window_size"),
+        window_overlap: values.get("window_overlap"),
+        delay_limit: values.get("delay_limit"),
+        metric,
+        field_metrics,
+        weighting: values.get("weighting"),
+        max_trace_len: values.get("max_trace_len"),
+        fields,
+        field_weights,
+        learn_field_weights: values.get("learn_field_weights"),
+        trace_sample_size: values.get("trace_sample_size"),
+        user_sample_size: values.get("user_sample_size"),
+        target_trace_sample_size: values.get("target_trace_sample_size"),
+        split_strategy: values.get("split_strategy"),
+        split_ratio: values.get("split_ratio"),
+        split_cutoff: matches
+            .value_of("split_cutoff")
+            .and_then(|value| values.parse("split_cutoff", value)),
+        num_folds: values.get("num_folds"),
+        gap_buckets: values.list("gap_buckets"),
+        top_k: values.list("top_k"),
+        max_trace_duration: values.get("max_trace_duration"),
+        min_trace_len: values.get("min_trace_len"),
+        min_num_traces: values.get("min_num_traces"),
+        path: values.get("path"),
+        schema,
+        snapshot: values.get("snapshot"),
+        snapshot_dir: values.get("snapshot_dir"),
+        path_to_map: values.get("path_to_map"),
+        load_map: values.get("load_map"),
+        seed: values.get("seed"),
+        typical: values.get("typical"),
+        multiple: values.get("multiple"),
+        dependent: values.get("dependent"),
+        strategy: values.get("strategy"),
+        scoring_matrix,
+        gap_open: matches
+            .value_of("gap_open")
+            .and_then(|value| values.parse("gap_open", value)),
+        substitution: values.get("substitution"),
+        approach: values.get("approach"),
+        scope: values.get("scope"),
+        experiment: matches.value_of("experiment").map(String::from),
+        attack_log: matches.value_of("attack_log").map(String::from),
+        attack_log_candidates: values.get("attack_log_candidates"),
+        bootstrap_samples: values.get("bootstrap_samples"),
+        confidence_level: values.get("confidence_level"),
+        compare: matches
+            .values_of("compare")
+            .map(|values| values.map(String::from).collect()),
+        unseen_fraction: values.get("unseen_fraction"),
+        match_threshold: matches
+            .value_of("match_threshold")
+            .and_then(|value| values.parse("match_threshold", value)),
+        acceptance: values.get("acceptance"),
+        acceptance_threshold: matches
+            .value_of("acceptance_threshold")
+            .and_then(|value| values.parse("acceptance_threshold", value)),
+        run: None,
     };
+
+    let mut problems = values.problems;
+    problems.extend(config.validate());
+    if !problems.is_empty() {
+        return Err(ConfigError::Invalid(problems));
+    }
     Ok(config)
 }
+
+/// Parses the values of the matched arguments and collects the problems instead of stopping at the first.
+struct Values<'a> {
+    matches: &'a clap::ArgMatches,
+    problems: Vec<String>,
+}
+
+impl Values<'_> {
+    fn get<T>(&mut self, name: &str) -> T
+    where
+        T: FromStr + Default,
+        T::Err: Display,
+    {
+        let value = self.matches.value_of(name).unwrap_or_default();
+        self.parse(name, value).unwrap_or_default()
+    }
+
+    fn list<T>(&mut self, name: &str) -> Vec<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let matches = self.matches;
+        matches
+            .values_of(name)
+            .map(|values| values.filter_map(|value| self.parse(name, value)).collect())
+            .unwrap_or_default()
+    }
+
+    fn parse<T>(&mut self, name: &str, value: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match value.parse::<T>() {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.problems.push(format!("Invalid value {:?} for {}: {}", value, name, e));
+                None
+            }
+        }
+    }
+}
+
+impl Config {
+    /// Returns the number of candidates to keep per linkage attack, which is 0 if the attacks are not logged.
+    pub fn num_logged_candidates(&self) -> usize {
+        if self.attack_log.is_some() {
+            self.attack_log_candidates
+        } else {
+            0
+        }
+    }
+
+    /// Returns the path of an output of this run. Runs of an experiment write their outputs to paths with the
+    /// (one-based) index of the run, e.g. `tmp/user_evaluation.run2`, such that they do not overwrite each other.
+    pub fn run_path(&self, path: &str) -> String {
+        match self.run {
+            Some(run) => utils::insert_suffix(path, &format!("run{}", run + 1)),
+            None => path.to_string(),
+        }
+    }
+
+    /// Returns every data field whose column is missing from the dataset, which is only known once the
+    /// headers of the dataset are read. Missing optional columns would otherwise be compared as constants.
+    pub fn validate_columns(&self, columns: &HashSet<Column>) -> Vec<String> {
+        self.fields
+            .iter()
+            .filter(|field| !columns.contains(&field.column()))
+            .map(|field| {
+                format!(
+                    "The data field {} requires the column {}, but the header {:?} is not found in {}",
+                    field,
+                    field.column(),
+                    self.schema.header(&field.column()),
+                    self.path
+                )
+            })
+            .collect()
+    }
+
+    /// Returns a problem if fewer users than `user_sample_size` can be sampled as target users, which is
+    /// only known after parsing and splitting the histories.
+    pub fn validate_num_users(&self, num_users: usize) -> Vec<String> {
+        if num_users < self.user_sample_size {
+            vec![format!(
+                "user_sample_size ({}) is larger than the {} users with target traces left after filtering",
+                self.user_sample_size, num_users
+            )]
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Returns every option that is invalid on its own or inconsistent with other options.
+    ///
+    /// The number of users is only known after parsing, so sample sizes are checked against the
+    /// number of traces every remaining user is guaranteed to have.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems: Vec<String> = Vec::new();
+
+        if self.fields.is_empty() {
+            problems.push("At least one data field has to be considered".to_string());
+        }
+        for field in self.fields.iter() {
+            if *field == DataFields::ActiveSeconds {
+                problems.push(format!("The data field {} is not supported by either approach", field));
+            }
+        }
+        if self.approach == Approach::Frequency {
+            for (field, metric) in self.fields.iter().zip(self.field_metrics.iter()) {
+                if metric.is_circular() && !matches!(field, DataFields::Hour | DataFields::Day) {
+                    problems.push(format!(
+                        "The metric {} is only supported by the data fields hour and day, not by {}",
+                        metric, field
+                    ));
+                }
+            }
+        }
+
+        if self.approach == Approach::Sequence && self.scoring_matrix.equal <= 0 {
+            problems.push(format!(
+                "The equal score of the scoring matrix ({}) has to be positive to normalize the alignment scores",
+                self.scoring_matrix.equal
+            ));
+        }
+        if let Some(gap_open) = self.gap_open
+            && (!gap_open.is_finite() || gap_open > 0.0)
+        {
+            problems.push(format!("gap_open ({}) has to be a finite score that is not positive", gap_open));
+        }
+
+        match self.session_strategy {
+            SessionStrategy::Inactivity | SessionStrategy::DomainChange if self.delay_limit <= 0.0 => {
+                problems.push(format!("delay_limit ({}) has to be positive", self.delay_limit));
+            }
+            SessionStrategy::SlidingWindow if self.window_size == 0 => {
+                problems.push("window_size has to be positive".to_string());
+            }
+            SessionStrategy::SlidingWindow if self.window_overlap >= self.window_size => {
+                problems.push(format!(
+                    "window_overlap ({}) has to be smaller than window_size ({})",
+                    self.window_overlap, self.window_size
+                ));
+            }
+            _ => (),
+        }
+
+        if self.min_trace_len == 0 {
+            problems.push("min_trace_len has to be positive".to_string());
+        }
+        if self.min_trace_len > self.max_trace_len {
+            problems.push(format!(
+                "min_trace_len ({}) is larger than max_trace_len ({})",
+                self.min_trace_len, self.max_trace_len
+            ));
+        }
+        if self.max_trace_duration < 0.0 {
+            problems.push(format!(
+                "max_trace_duration ({}) must not be negative",
+                self.max_trace_duration
+            ));
+        }
+
+        // The history of every user is split into observed and target traces
+        if self.min_num_traces < 2 {
+            problems.push(format!(
+                "min_num_traces ({}) has to be at least 2 to split the history into observed and target traces",
+                self.min_num_traces
+            ));
+        }
+        let num_problems = problems.len();
+        match self.split_strategy {
+            SplitStrategy::Chronological | SplitStrategy::Random
+                if self.split_ratio <= 0.0 || self.split_ratio >= 1.0 =>
+            {
+                problems.push(format!(
+                    "split_ratio ({}) has to be between 0 and 1",
+                    self.split_ratio
+                ));
+            }
+            SplitStrategy::Cutoff if self.split_cutoff.is_none() => {
+                problems.push("The cutoff split strategy requires a split_cutoff".to_string());
+            }
+            SplitStrategy::KFold if self.num_folds < 2 => {
+                problems.push(format!("num_folds ({}) has to be at least 2", self.num_folds));
+            }
+            SplitStrategy::KFold if self.num_folds > self.min_num_traces => {
+                problems.push(format!(
+                    "num_folds ({}) is larger than min_num_traces ({}), such that some folds would lack target traces",
+                    self.num_folds, self.min_num_traces
+                ));
+            }
+            _ => (),
+        }
+        // The splitter can only be created from a valid split configuration
+        let split_is_valid = problems.len() == num_problems;
+        if self.user_sample_size == 0 {
+            problems.push("user_sample_size has to be positive".to_string());
+        }
+        if self.trace_sample_size == 0 {
+            problems.push("trace_sample_size has to be positive".to_string());
+        }
+        if self.target_trace_sample_size == 0 {
+            problems.push("target_trace_sample_size has to be positive".to_string());
+        } else if split_is_valid
+            && let Some(num_target_traces) =
+                split::from_config(self).min_num_targets(self.min_num_traces)
+            && self.target_trace_sample_size > num_target_traces
+        {
+            problems.push(format!(
+                "target_trace_sample_size ({}) is larger than the {} target traces of users with min_num_traces ({}) traces under the {} split strategy",
+                self.target_trace_sample_size, num_target_traces, self.min_num_traces, self.split_strategy
+            ));
+        }
+
+        if self.field_weights.iter().any(|weight| !weight.is_finite() || *weight < 0.0)
+            || self.field_weights.iter().all(|weight| *weight == 0.0)
+        {
+            problems.push(format!(
+                "The field weights ({:?}) have to be non-negative and not all zero",
+                self.field_weights
+            ));
+        }
+        if !self.gap_buckets.windows(2).all(|edges| edges[0] < edges[1]) {
+            problems.push(format!(
+                "The edges of gap_buckets ({:?}) have to be strictly increasing",
+                self.gap_buckets
+            ));
+        }
+
+        if self.top_k.contains(&0) {
+            problems.push("The ranks of top_k have to be positive".to_string());
+        }
+
+        if self.dependent && self.multiple {
+            problems.push(
+                "multiple is not supported by dependent linkage attacks, set dependent to false".to_string(),
+            );
+        }
+        if self.dependent
+            && self.acceptance_threshold.is_none()
+            && self.acceptance.default_threshold().is_none()
+        {
+            problems.push(format!(
+                "The acceptance rule {} requires an acceptance_threshold",
+                self.acceptance
+            ));
+        }
+        if self.typical && self.multiple {
+            problems.push("typical and multiple cannot be combined".to_string());
+        }
+
+        if !(0.0..1.0).contains(&self.unseen_fraction) {
+            problems.push(format!(
+                "unseen_fraction ({}) has to be at least 0 and smaller than 1",
+                self.unseen_fraction
+            ));
+        }
+
+        if self.bootstrap_samples == 0 {
+            problems.push("bootstrap_samples has to be positive".to_string());
+        }
+        if self.confidence_level <= 0.0 || self.confidence_level >= 1.0 {
+            problems.push(format!(
+                "confidence_level ({}) has to be between 0 and 1",
+                self.confidence_level
+            ));
+        }
+
+        if let Some(paths) = &self.compare {
+            for path in paths.iter().filter(|path| !Path::new(path).is_file()) {
+                problems.push(format!("The attack log {:?} does not exist", path));
+            }
+            // No dataset is needed to compare two runs
+            return problems;
+        }
+        if let Some(path) = &self.experiment {
+            if !Path::new(path).is_file() {
+                problems.push(format!("The experiment {:?} does not exist", path));
+            }
+            // The dataset of every run is checked when the runs of the experiment are generated
+            return problems;
+        }
+
+        if !Path::new(&self.path).is_file() {
+            problems.push(format!("The dataset {:?} does not exist", self.path));
+        }
+        if self.load_map {
+            let num_folds = if split_is_valid { split::from_config(self).num_folds() } else { 1 };
+            for fold in 0..num_folds {
+                let path = split::fold_path(&self.path_to_map, fold, num_folds);
+                if !Path::new(&path).is_file() {
+                    problems.push(format!(
+                        "The sampled maps {:?} do not exist, set load_map to false to sample them",
+                        path
+                    ));
+                }
+            }
+        }
+        problems
+    }
+}
+
+/// Loads the configuration from the given arguments for a small dataset in the temporary directory.
+#[cfg(test)]
+pub fn test_config(args: &[&str]) -> Config {
+    let path = std::env::temp_dir().join(format!("test_config_{}.csv", std::process::id()));
+    std::fs::write(&path, "panelist_id,used_at,url\na,1,x.com\n").unwrap();
+    let path = path.to_str().unwrap().to_string();
+    let mut all_args = vec!["tracking-by-browsing-behavior", "--path", &path];
+    all_args.extend_from_slice(args);
+    get_config_from(all_args).unwrap()
+}
@@ -0,0 +1,135 @@
+use crate::cli::Config;
+use crate::stats;
+use crate::utils::{self, LoggedAttack};
+
+use csv::WriterBuilder;
+use rand::{rngs::StdRng, SeedableRng};
+use serde::Serialize;
+use std::{collections::HashMap, error::Error};
+
+const COMPARISON_PATH: &str = "tmp/comparison";
+
+#[derive(Serialize)]
+struct ComparisonRow<'a> {
+    first: &'a str,
+    second: &'a str,
+    k: usize,
+    num_attacks: usize,
+    first_accuracy: f64,
+    second_accuracy: f64,
+    diff: f64,
+    diff_ci_low: f64,
+    diff_ci_high: f64,
+    only_first: usize,
+    only_second: usize,
+    mcnemar_p: f64,
+    bootstrap_p: f64,
+    significant: bool,
+}
+
+/// Compares the top-k accuracies of two runs given their attack logs.
+///
+/// The runs have to perform the same linkage attacks, e.g. by loading the same sampled maps. The
+/// difference is tested with McNemar's test and a paired bootstrap, and it is considered significant
+/// if both p-values are below one minus the confidence level. The results are appended to the comparison file.
+pub fn compare(config: &Config, first_path: &str, second_path: &str) -> Result<(), Box<dyn Error>> {
+    let first_attacks = utils::read_attack_log(first_path)?;
+    let second_attacks = utils::read_attack_log(second_path)?;
+    let (first_rank_list, second_rank_list) = pair_ranks(&first_attacks, &second_attacks)?;
+    log::info!(
+        "Comparing {:?} and {:?} on {} linkage attacks",
+        first_path,
+        second_path,
+        first_rank_list.len()
+    );
+
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let alpha = 1.0 - config.confidence_level;
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(COMPARISON_PATH)?;
+    let mut wtr = WriterBuilder::new()
+        .delimiter(b',')
+        .has_headers(false)
+        .from_writer(file);
+
+    for k in config.top_k.iter() {
+        let first_list = stats::to_share_list(first_rank_list.iter().map(|rank| rank <= k));
+        let second_list = stats::to_share_list(second_rank_list.iter().map(|rank| rank <= k));
+        let comparison = stats::compare_paired(
+            &first_list,
+            &second_list,
+            &mut rng,
+            config.bootstrap_samples,
+            config.confidence_level,
+        )?;
+        let first_accuracy = utils::mean(&first_list);
+        let second_accuracy = utils::mean(&second_list);
+        let significant = comparison.mcnemar_p < alpha && comparison.bootstrap_p < alpha;
+        log::info!(
+            "Top {}: {} vs. {}, difference {} [{}, {}], McNemar p = {}, bootstrap p = {}{}",
+            k,
+            first_accuracy,
+            second_accuracy,
+            second_accuracy - first_accuracy,
+            comparison.diff_ci.0,
+            comparison.diff_ci.1,
+            comparison.mcnemar_p,
+            comparison.bootstrap_p,
+            if significant { " (significant)" } else { "" }
+        );
+        wtr.serialize(ComparisonRow {
+            first: first_path,
+            second: second_path,
+            k: *k,
+            num_attacks: first_list.len(),
+            first_accuracy,
+            second_accuracy,
+            diff: second_accuracy - first_accuracy,
+            diff_ci_low: comparison.diff_ci.0,
+            diff_ci_high: comparison.diff_ci.1,
+            only_first: comparison.only_first,
+            only_second: comparison.only_second,
+            mcnemar_p: comparison.mcnemar_p,
+            bootstrap_p: comparison.bootstrap_p,
+            significant,
+        })?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Pairs the ranks of the attacks on the same target user and trace. Both logs have to contain exactly the same attacks.
+fn pair_ranks(
+    first_attacks: &[LoggedAttack],
+    second_attacks: &[LoggedAttack],
+) -> Result<(Vec<usize>, Vec<usize>), Box<dyn Error>> {
+    let mut second_rank_map: HashMap<(&str, Option<usize>), usize> = HashMap::new();
+    for attack in second_attacks.iter() {
+        let key = (attack.original_user_id.as_str(), attack.target_idx);
+        if second_rank_map.insert(key, attack.rank).is_some() {
+            return Err(format!("Duplicate linkage attack on {:?} in the second log", key).into());
+        }
+    }
+    if first_attacks.len() != second_rank_map.len() {
+        return Err(format!(
+            "The logs contain a different number of linkage attacks: {} and {}",
+            first_attacks.len(),
+            second_rank_map.len()
+        )
+        .into());
+    }
+
+    let mut first_rank_list: Vec<usize> = Vec::with_capacity(first_attacks.len());
+    let mut second_rank_list: Vec<usize> = Vec::with_capacity(first_attacks.len());
+    for attack in first_attacks.iter() {
+        let key = (attack.original_user_id.as_str(), attack.target_idx);
+        let second_rank = second_rank_map
+            .remove(&key)
+            .ok_or_else(|| format!("The linkage attack on {:?} is missing in the second log", key))?;
+        first_rank_list.push(attack.rank);
+        second_rank_list.push(second_rank);
+    }
+    Ok((first_rank_list, second_rank_list))
+}
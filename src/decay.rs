@@ -0,0 +1,188 @@
+use crate::split::TimedTrace;
+use crate::utils::{self, AttackResult, UserResult};
+
+use csv::WriterBuilder;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+};
+
+pub const GAP_EVAL_PATH: &str = "tmp/gap_evaluation";
+const SECONDS_PER_DAY: f64 = 86400.0;
+
+/// Sets the time gap of every linkage attack, i.e. the number of days between the end of the latest
+/// observed trace of the target user and the start of the target trace.
+///
+/// The gap is negative if the target trace precedes an observed trace, e.g. for random splits. It stays
+/// unknown for attacks that combine several target traces and for target users without observed traces.
+pub fn set_gaps<T: TimedTrace>(
+    user_result_list: &mut [UserResult],
+    user_to_vector_map: &BTreeMap<u32, Vec<T>>,
+    user_to_sample_idx_map: &HashMap<u32, Vec<usize>>,
+) {
+    for (user_target, result_list) in user_result_list.iter_mut() {
+        let traces_list = user_to_vector_map.get(user_target).unwrap();
+        let latest_observed_time = user_to_sample_idx_map.get(user_target).and_then(|samples_idx| {
+            samples_idx
+                .iter()
+                .map(|idx| traces_list[*idx].end_time())
+                .max_by(|a, b| a.total_cmp(b))
+        });
+        for result in result_list.iter_mut() {
+            result.gap_days = result
+                .target_idx
+                .zip(latest_observed_time)
+                .map(|(target_idx, observed_time)| {
+                    (traces_list[target_idx].start_time() - observed_time) / SECONDS_PER_DAY
+                });
+        }
+    }
+}
+
+/// Accuracy of the linkage attacks whose time gap lies within `[gap_from, gap_to)` days.
+#[derive(Debug, Serialize)]
+pub struct GapBucket {
+    pub gap_from: f64,
+    pub gap_to: f64,
+    pub num_attacks: usize,
+    pub top_1: f64,
+    pub top_10: f64,
+    pub mrr: f64,
+    pub median_rank: f64,
+}
+
+/// Groups the linkage attacks with a known time gap into buckets separated by the given edges (in days)
+/// and computes the accuracy per bucket. The first and the last bucket are unbounded, buckets without
+/// linkage attacks are omitted.
+pub fn gen_buckets(result_list: &[AttackResult], edges: &[f64]) -> Vec<GapBucket> {
+    let bounds: Vec<f64> = std::iter::once(f64::NEG_INFINITY)
+        .chain(edges.iter().cloned())
+        .chain(std::iter::once(f64::INFINITY))
+        .collect();
+    bounds
+        .windows(2)
+        .filter_map(|window| {
+            let (gap_from, gap_to) = (window[0], window[1]);
+            let bucket_list: Vec<&AttackResult> = result_list
+                .iter()
+                .filter(|result| result.gap_days.is_some_and(|gap| gap_from <= gap && gap < gap_to))
+                .collect();
+            if bucket_list.is_empty() {
+                return None;
+            }
+            let share = |is_hit: &dyn Fn(&AttackResult) -> bool| {
+                bucket_list.iter().filter(|result| is_hit(result)).count() as f64
+                    / bucket_list.len() as f64
+            };
+            let rank_list: Vec<f64> = bucket_list.iter().map(|result| result.rank as f64).collect();
+            Some(GapBucket {
+                gap_from,
+                gap_to,
+                num_attacks: bucket_list.len(),
+                top_1: share(&|result| result.is_in_top_k(1)),
+                top_10: share(&|result| result.is_in_top_k(10)),
+                mrr: utils::mean(&rank_list.iter().map(|rank| 1.0 / rank).collect::<Vec<f64>>()),
+                median_rank: utils::median(&rank_list),
+            })
+        })
+        .collect()
+}
+
+/// Writes the buckets to the given file.
+pub fn write_buckets(path: &str, bucket_list: &[GapBucket]) -> Result<(), Box<dyn Error>> {
+    let mut wtr = WriterBuilder::new()
+        .delimiter(b',')
+        .has_headers(true)
+        .from_path(path)?;
+    for bucket in bucket_list.iter() {
+        wtr.serialize(bucket)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ordered_float::OrderedFloat;
+
+    const EPS: f64 = 1e-12;
+
+    struct Trace {
+        start_time: f64,
+        end_time: f64,
+    }
+
+    impl TimedTrace for Trace {
+        fn start_time(&self) -> f64 {
+            self.start_time
+        }
+
+        fn end_time(&self) -> f64 {
+            self.end_time
+        }
+    }
+
+    fn day(days: f64) -> f64 {
+        days * SECONDS_PER_DAY
+    }
+
+    fn attack(target_idx: Option<usize>, rank: usize, gap_days: Option<f64>) -> AttackResult {
+        let mut result = AttackResult::from_sorted_tuples(&0, target_idx, &[(0, OrderedFloat(1.0))], 0);
+        result.rank = rank;
+        result.gap_days = gap_days;
+        result
+    }
+
+    #[test]
+    fn gaps_start_at_the_latest_observed_trace() {
+        let traces = vec![
+            Trace { start_time: day(0.0), end_time: day(0.5) },
+            Trace { start_time: day(1.0), end_time: day(1.5) },
+            Trace { start_time: day(2.0), end_time: day(2.5) },
+            Trace { start_time: day(4.0), end_time: day(4.5) },
+        ];
+        let user_to_vector_map = BTreeMap::from([(0, traces), (1, Vec::new())]);
+        // The observed traces are not ordered, the latest one ends after 2.5 days
+        let user_to_sample_idx_map = HashMap::from([(0, vec![2, 0])]);
+        let mut user_result_list: Vec<UserResult> = vec![
+            (0, vec![attack(Some(3), 1, None), attack(Some(1), 1, None), attack(None, 1, None)]),
+            (1, vec![attack(Some(0), 1, None)]),
+        ];
+        set_gaps(&mut user_result_list, &user_to_vector_map, &user_to_sample_idx_map);
+
+        let gap_list: Vec<Option<f64>> = user_result_list[0].1.iter().map(|result| result.gap_days).collect();
+        assert!((gap_list[0].unwrap() - 1.5).abs() < EPS);
+        // Target traces that precede an observed trace have a negative gap
+        assert!((gap_list[1].unwrap() + 1.5).abs() < EPS);
+        // Combined target traces and users without observed traces have no gap
+        assert_eq!(gap_list[2], None);
+        assert_eq!(user_result_list[1].1[0].gap_days, None);
+    }
+
+    #[test]
+    fn buckets_include_their_lower_edge() {
+        let result_list = vec![
+            attack(Some(0), 1, Some(-0.5)),
+            attack(Some(0), 1, Some(1.0)),
+            attack(Some(0), 2, Some(6.9)),
+            attack(Some(0), 20, Some(7.0)),
+            attack(None, 1, None),
+        ];
+        let bucket_list = gen_buckets(&result_list, &[1.0, 7.0, 30.0]);
+        let bounds: Vec<(f64, f64)> = bucket_list.iter().map(|bucket| (bucket.gap_from, bucket.gap_to)).collect();
+        // The bucket [30, inf) is empty and thus omitted
+        assert_eq!(bounds, vec![(f64::NEG_INFINITY, 1.0), (1.0, 7.0), (7.0, 30.0)]);
+        assert_eq!(bucket_list.iter().map(|bucket| bucket.num_attacks).collect::<Vec<usize>>(), vec![1, 2, 1]);
+
+        let bucket = &bucket_list[1];
+        assert!((bucket.top_1 - 0.5).abs() < EPS);
+        assert!((bucket.top_10 - 1.0).abs() < EPS);
+        assert!((bucket.mrr - 0.75).abs() < EPS);
+        assert!((bucket.median_rank - 1.5).abs() < EPS);
+        assert!((bucket_list[2].top_10 - 0.0).abs() < EPS);
+
+        assert!(gen_buckets(&[], &[1.0]).is_empty());
+    }
+}
@@ -0,0 +1,232 @@
+use crate::cli::{self, Config};
+
+use serde::Deserialize;
+use std::{collections::BTreeMap, error::Error, fs, path::Path};
+
+/// An experiment consists of base parameters shared by all runs and a grid of parameters to sweep over.
+///
+/// The parameter names are the same as on the command line, e.g.
+/// ```toml
+/// [base]
+/// approach = "frequency"
+/// fields = ["url", "domain"]
+///
+/// [grid]
+/// metric = ["cosine", "euclidean"]
+/// user_sample_size = [100, 200]
+/// ```
+/// runs the evaluation once for each of the four combinations of metric and user sample size.
+#[derive(Debug, Deserialize)]
+pub struct Experiment {
+    #[serde(default)]
+    base: BTreeMap<String, ArgValue>,
+    #[serde(default)]
+    grid: BTreeMap<String, Vec<ArgValue>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum ArgValue {
+    Scalar(Scalar),
+    List(Vec<Scalar>),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum Scalar {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+}
+
+impl Scalar {
+    fn to_arg(&self) -> String {
+        match self {
+            Scalar::Bool(value) => value.to_string(),
+            Scalar::Int(value) => value.to_string(),
+            Scalar::Float(value) => value.to_string(),
+            Scalar::Str(value) => value.clone(),
+        }
+    }
+}
+
+impl ArgValue {
+    fn to_args(&self) -> Vec<String> {
+        match self {
+            ArgValue::Scalar(scalar) => vec![scalar.to_arg()],
+            ArgValue::List(scalars) => scalars.iter().map(Scalar::to_arg).collect(),
+        }
+    }
+}
+
+impl Experiment {
+    /// Loads an experiment from file. Files ending with `.json` are read as JSON, all others as TOML.
+    pub fn from_file(path: &str) -> Result<Experiment, Box<dyn Error>> {
+        let content = fs::read_to_string(path)?;
+        let is_json = Path::new(path)
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+        let experiment = if is_json {
+            serde_json::from_str(&content)?
+        } else {
+            toml::from_str(&content)?
+        };
+        Ok(experiment)
+    }
+
+    /// Generates the configuration of every combination of grid values, in the order of the grid. Grid values
+    /// take precedence over base values of the same parameter.
+    pub fn gen_configs(&self) -> Result<Vec<Config>, Box<dyn Error>> {
+        let mut combinations: Vec<BTreeMap<&str, &ArgValue>> = vec![BTreeMap::new()];
+        for (name, values) in self.grid.iter() {
+            if values.is_empty() {
+                return Err(format!("The grid of {:?} does not contain any values", name).into());
+            }
+            combinations = combinations
+                .into_iter()
+                .flat_map(|combination| {
+                    values.iter().map(move |value| {
+                        let mut combination = combination.clone();
+                        combination.insert(name.as_str(), value);
+                        combination
+                    })
+                })
+                .collect();
+        }
+
+        let mut configs: Vec<Config> = Vec::with_capacity(combinations.len());
+        for (run, combination) in combinations.into_iter().enumerate() {
+            let mut params: BTreeMap<&str, &ArgValue> =
+                self.base.iter().map(|(name, value)| (name.as_str(), value)).collect();
+            params.extend(combination);
+
+            let mut args: Vec<String> = vec![env!("CARGO_PKG_NAME").to_string()];
+            for (name, value) in params.iter() {
+                args.push(format!("--{}", name));
+                args.extend(value.to_args());
+            }
+            let mut config = cli::get_config_from(&args)
+                .map_err(|e| format!("Invalid parameters {:?}: {}", &args[1..], e))?;
+            config.run = Some(run);
+            configs.push(config);
+        }
+        Ok(configs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frequency::metrics::DistanceMetric;
+
+    /// Writes the content to a file in the temporary directory that is unique per test.
+    fn write_file(name: &str, content: &str) -> String {
+        let path = std::env::temp_dir().join(format!("experiment_{}_{}", std::process::id(), name));
+        fs::write(&path, content).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    /// Parses the experiment and runs it on a small dataset.
+    fn parse(content: &str) -> Experiment {
+        let mut experiment: Experiment = toml::from_str(content).unwrap();
+        let path = write_file("browsing.csv", "panelist_id,used_at,url\na,1,x.com\n");
+        experiment.base.insert("path".to_string(), ArgValue::Scalar(Scalar::Str(path)));
+        experiment
+    }
+
+    #[test]
+    fn grid_yields_the_cartesian_product_in_order() {
+        let experiment = parse(
+            r#"
+            [base]
+            approach = "frequency"
+            fields = ["url", "domain"]
+
+            [grid]
+            metric = ["cosine", "euclidean"]
+            user_sample_size = [100, 200, 300]
+            "#,
+        );
+        let configs = experiment.gen_configs().unwrap();
+        let combinations: Vec<(DistanceMetric, usize)> =
+            configs.iter().map(|config| (config.metric, config.user_sample_size)).collect();
+        assert_eq!(
+            combinations,
+            vec![
+                (DistanceMetric::Cosine, 100),
+                (DistanceMetric::Cosine, 200),
+                (DistanceMetric::Cosine, 300),
+                (DistanceMetric::Euclidean, 100),
+                (DistanceMetric::Euclidean, 200),
+                (DistanceMetric::Euclidean, 300),
+            ]
+        );
+        for (run, config) in configs.iter().enumerate() {
+            assert_eq!(config.run, Some(run));
+            assert_eq!(config.approach, cli::Approach::Frequency);
+            assert_eq!(config.fields.len(), 2);
+        }
+        assert_eq!(configs[1].run_path("tmp/user_evaluation"), "tmp/user_evaluation.run2");
+        assert_eq!(configs[5].run_path("tmp/attacks.csv"), "tmp/attacks.run6.csv");
+    }
+
+    #[test]
+    fn grid_values_override_base_values() {
+        let experiment = parse(
+            r#"
+            [base]
+            seed = 1
+            delay_limit = 600
+
+            [grid]
+            delay_limit = [60.5, 120]
+            "#,
+        );
+        let configs = experiment.gen_configs().unwrap();
+        let delay_limits: Vec<f64> = configs.iter().map(|config| config.delay_limit).collect();
+        assert_eq!(delay_limits, vec![60.5, 120.0]);
+        assert!(configs.iter().all(|config| config.seed == 1));
+
+        // Without a grid, the base is run once
+        let configs = parse("[base]\nseed = 2").gen_configs().unwrap();
+        assert_eq!(configs.len(), 1);
+        assert_eq!(configs[0].seed, 2);
+    }
+
+    #[test]
+    fn empty_grid_is_rejected() {
+        let experiment = parse("[grid]\nmetric = []");
+        let e = experiment.gen_configs().unwrap_err();
+        assert!(e.to_string().contains("\"metric\""), "{}", e);
+    }
+
+    #[test]
+    fn invalid_combination_is_rejected() {
+        let experiment = parse(
+            r#"
+            [base]
+            session_strategy = "sliding_window"
+            window_size = 3
+
+            [grid]
+            window_overlap = [1, 3]
+            "#,
+        );
+        let e = experiment.gen_configs().unwrap_err();
+        assert!(e.to_string().contains("window_overlap (3)"), "{}", e);
+    }
+
+    #[test]
+    fn options_next_to_an_experiment_are_rejected() {
+        let path = write_file("grid.toml", "[grid]\nseed = [1, 2]");
+        let args = ["tracking-by-browsing-behavior", "--experiment", &path, "--seed", "3"];
+        match cli::get_config_from(args) {
+            Err(cli::ConfigError::Invalid(problems)) => {
+                assert!(problems.iter().any(|problem| problem.contains("--seed")), "{:?}", problems)
+            }
+            result => panic!("Options are accepted: {:?}", result.map(|_| ())),
+        }
+        assert!(cli::get_config_from(["tracking-by-browsing-behavior", "--experiment", &path]).is_ok());
+    }
+}
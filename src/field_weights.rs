@@ -0,0 +1,211 @@
+use crate::cli::Config;
+use crate::parse::DataFields;
+
+use rand::{prelude::SliceRandom, rngs::StdRng, SeedableRng};
+use rayon::prelude::*;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+};
+
+/// The weights tried for a single data field while learning the weights, relative to the other fields.
+const CANDIDATE_WEIGHTS: [f64; 6] = [0.0, 0.25, 0.5, 1.0, 2.0, 4.0];
+const MAX_ROUNDS: usize = 10;
+
+/// The weights with which the scores of the individual data fields are combined into a single score.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldWeights {
+    /// The weight of every data field in the order of the configured fields, summing up to one.
+    pub weights: Vec<f64>,
+    /// The top 1 accuracy on the validation split, if the weights were learned.
+    pub validation_top_1: Option<f64>,
+}
+
+impl FieldWeights {
+    /// Returns the weights given in the configuration.
+    pub fn from_config(config: &Config) -> FieldWeights {
+        FieldWeights {
+            weights: normalize(&config.field_weights),
+            validation_top_1: None,
+        }
+    }
+
+    /// Combines the scores of the individual data fields into their weighted mean.
+    pub fn combine(&self, scores: &[f64]) -> f64 {
+        self.weights.iter().zip(scores.iter()).map(|(weight, score)| weight * score).sum()
+    }
+
+    /// Averages the weights of several folds.
+    pub fn mean(field_weights_list: &[FieldWeights]) -> FieldWeights {
+        let num_folds = field_weights_list.len() as f64;
+        let num_fields = field_weights_list.first().map_or(0, |field_weights| field_weights.weights.len());
+        let weights = (0..num_fields)
+            .map(|position| {
+                field_weights_list.iter().map(|field_weights| field_weights.weights[position]).sum::<f64>()
+                    / num_folds
+            })
+            .collect();
+        let validation_top_1_list: Vec<f64> =
+            field_weights_list.iter().filter_map(|field_weights| field_weights.validation_top_1).collect();
+        FieldWeights {
+            weights,
+            validation_top_1: (!validation_top_1_list.is_empty())
+                .then(|| validation_top_1_list.iter().sum::<f64>() / validation_top_1_list.len() as f64),
+        }
+    }
+
+    /// Formats the weights as `field:weight` pairs, e.g. `Url:0.5 Domain:0.5`.
+    pub fn format(&self, fields: &[DataFields]) -> String {
+        fields
+            .iter()
+            .zip(self.weights.iter())
+            .map(|(field, weight)| format!("{}:{}", field, weight))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+fn normalize(weights: &[f64]) -> Vec<f64> {
+    let sum: f64 = weights.iter().sum();
+    weights.iter().map(|weight| weight / sum).collect()
+}
+
+/// The scores of the individual data fields between a validation target and every observed trace of a candidate.
+struct ValidationAttack {
+    user_target: u32,
+    user_to_scores_map: Vec<(u32, Vec<Vec<f64>>)>,
+}
+
+/// Learns the weights of the data fields on a validation split of the observed traces, such that the top 1
+/// accuracy is maximized (the mean reciprocal rank breaks ties).
+///
+/// The latest sampled observed trace of each candidate user is held out as validation target and linked to
+/// the remaining observed traces, i.e. the target traces of the evaluation are never used. As the scores of
+/// the individual fields only have to be computed once, the weights are searched by coordinate ascent.
+pub fn learn<T, F>(
+    config: &Config,
+    user_to_traces_map: &BTreeMap<u32, Vec<T>>,
+    user_to_sample_idx_map: &HashMap<u32, Vec<usize>>,
+    field_scores: F,
+) -> FieldWeights
+where
+    T: Sync,
+    F: Fn(&T, &T) -> Vec<f64> + Sync,
+{
+    // Hold out the latest observed trace of every candidate with further observed traces
+    let mut user_to_history_map: BTreeMap<u32, Vec<&T>> = BTreeMap::new();
+    let mut validation_list: Vec<(u32, &T)> = Vec::new();
+    for (user, traces) in user_to_traces_map.iter() {
+        let Some(samples_idx) = user_to_sample_idx_map.get(user) else {
+            continue;
+        };
+        let mut samples_idx = samples_idx.clone();
+        samples_idx.sort_unstable();
+        if samples_idx.len() > 1 {
+            validation_list.push((*user, &traces[samples_idx.pop().unwrap()]));
+        }
+        user_to_history_map.insert(*user, samples_idx.iter().map(|idx| &traces[*idx]).collect());
+    }
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let validation_list: Vec<&(u32, &T)> =
+        validation_list.choose_multiple(&mut rng, config.user_sample_size).collect();
+    log::info!("Learning field weights on {} validation targets...", validation_list.len());
+
+    let attack_list: Vec<ValidationAttack> = validation_list
+        .par_iter()
+        .map(|(user_target, target_trace)| ValidationAttack {
+            user_target: *user_target,
+            user_to_scores_map: user_to_history_map
+                .iter()
+                .map(|(user, history)| {
+                    let scores_list = history.iter().map(|trace| field_scores(target_trace, trace)).collect();
+                    (*user, scores_list)
+                })
+                .collect(),
+        })
+        .collect();
+
+    let higher_is_better = config.approach.higher_is_better();
+    let mut weights = config.field_weights.clone();
+    let mut best = objective(&attack_list, &weights, higher_is_better);
+    for _ in 0..MAX_ROUNDS {
+        let mut improved = false;
+        for position in 0..weights.len() {
+            for candidate in CANDIDATE_WEIGHTS.iter() {
+                let mut candidate_weights = weights.clone();
+                candidate_weights[position] = *candidate;
+                if candidate_weights.iter().all(|weight| *weight == 0.0) {
+                    continue;
+                }
+                let score = objective(&attack_list, &candidate_weights, higher_is_better);
+                if score > best {
+                    best = score;
+                    weights = candidate_weights;
+                    improved = true;
+                }
+            }
+        }
+        if !improved {
+            break;
+        }
+    }
+
+    let field_weights = FieldWeights {
+        weights: normalize(&weights),
+        validation_top_1: Some(best.0),
+    };
+    log::info!(
+        "Learned the field weights {} with a validation top 1 of {}",
+        field_weights.format(&config.fields),
+        best.0
+    );
+    field_weights
+}
+
+/// Returns the top 1 accuracy and the mean reciprocal rank of the validation attacks with the given weights.
+///
+/// Candidates with the same score as the target user are ranked first, such that equal scores, e.g. if all
+/// weights but one are zero, are not rewarded.
+fn objective(attack_list: &[ValidationAttack], weights: &[f64], higher_is_better: bool) -> (f64, f64) {
+    let rank_list: Vec<usize> = attack_list
+        .par_iter()
+        .map(|attack| {
+            let user_to_best_map: Vec<(u32, f64)> = attack
+                .user_to_scores_map
+                .iter()
+                .filter_map(|(user, scores_list)| {
+                    let best = scores_list
+                        .iter()
+                        .map(|scores| weights.iter().zip(scores.iter()).map(|(w, s)| w * s).sum::<f64>())
+                        .max_by(|a, b| compare(*a, *b, higher_is_better))?;
+                    Some((*user, best))
+                })
+                .collect();
+            let target_best = user_to_best_map
+                .iter()
+                .find(|(user, _)| *user == attack.user_target)
+                .map(|(_, best)| *best)
+                .unwrap();
+            1 + user_to_best_map
+                .iter()
+                .filter(|(user, best)| {
+                    *user != attack.user_target
+                        && compare(*best, target_best, higher_is_better) != Ordering::Less
+                })
+                .count()
+        })
+        .collect();
+    let num_attacks = rank_list.len().max(1) as f64;
+    let top_1 = rank_list.iter().filter(|rank| **rank == 1).count() as f64 / num_attacks;
+    let mrr = rank_list.iter().map(|rank| 1.0 / *rank as f64).sum::<f64>() / num_attacks;
+    (top_1, mrr)
+}
+
+/// Orders the scores such that the better score is greater.
+fn compare(a: f64, b: f64, higher_is_better: bool) -> Ordering {
+    if higher_is_better {
+        a.total_cmp(&b)
+    } else {
+        b.total_cmp(&a)
+    }
+}
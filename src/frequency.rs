@@ -1,4 +1,6 @@
 pub mod metrics;
 pub mod maths;
+pub mod sparse;
 pub mod trace;
+pub mod weighting;
 pub mod evaluation;
\ No newline at end of file
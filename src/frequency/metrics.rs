@@ -1,25 +1,30 @@
-use crate::frequency::maths;
+use crate::frequency::sparse::SparseVector;
 
-use num_traits::ToPrimitive;
-use nalgebra::EuclideanNorm;
-use nalgebra::LpNorm;
-use std::{f64::consts::E, str::FromStr};
+use std::{borrow::Cow, f64::consts::E, fmt::Display, str::FromStr};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub enum DistanceMetric {
     Euclidean,
     Manhattan,
     Cosine,
     NonIntersection,
     Bhattacharyya,
+    #[default]
     KullbrackLeibler,
+    KullbrackLeiblerReverse,
+    SymmetricKullbrackLeibler,
     TotalVariation,
     JeffriesMatusita,
     ChiSquared,
+    JensenShannon,
+    Hellinger,
+    Canberra,
+    BrayCurtis,
+    Wasserstein,
 }
 
 impl FromStr for DistanceMetric {
-    type Err = std::string::ParseError;
+    type Err = String;
     fn from_str(s: &str) -> Result<DistanceMetric, Self::Err> {
         match s {
             "euclidean" => Ok(DistanceMetric::Euclidean),
@@ -28,73 +33,159 @@ impl FromStr for DistanceMetric {
             "non_intersection" => Ok(DistanceMetric::NonIntersection),
             "bhattacharyya" => Ok(DistanceMetric::Bhattacharyya),
             "kullbrack_leibler" => Ok(DistanceMetric::KullbrackLeibler),
+            "kullbrack_leibler_reverse" => Ok(DistanceMetric::KullbrackLeiblerReverse),
+            "symmetric_kullbrack_leibler" => Ok(DistanceMetric::SymmetricKullbrackLeibler),
             "total_variation" => Ok(DistanceMetric::TotalVariation),
             "jeffries_matusita" => Ok(DistanceMetric::JeffriesMatusita),
             "chi_quared" => Ok(DistanceMetric::ChiSquared),
-            x => panic!("The supplied metric does not exist: {:?}", x),
+            "jensen_shannon" => Ok(DistanceMetric::JensenShannon),
+            "hellinger" => Ok(DistanceMetric::Hellinger),
+            "canberra" => Ok(DistanceMetric::Canberra),
+            "bray_curtis" => Ok(DistanceMetric::BrayCurtis),
+            "wasserstein" => Ok(DistanceMetric::Wasserstein),
+            x => Err(format!("The supplied metric does not exist: {:?}", x)),
         }
     }
 }
 
-pub fn euclidean_dist<T, U>(target_vec: Vec<T>, ref_vec: Vec<U>) -> f64
-where
-    T: Clone + std::cmp::PartialEq + std::fmt::Debug + ToPrimitive,
-    U: Clone + std::cmp::PartialEq + std::fmt::Debug + ToPrimitive,
-{
-    let target_matrix = maths::vec_to_matrix(target_vec, true);
-    let ref_matrix = maths::vec_to_matrix(ref_vec, true);
-    let dist = target_matrix.apply_metric_distance(&ref_matrix, &EuclideanNorm);
-    dist
+impl Display for DistanceMetric {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            DistanceMetric::Euclidean => "euclidean",
+            DistanceMetric::Manhattan => "manhattan",
+            DistanceMetric::Cosine => "cosine",
+            DistanceMetric::NonIntersection => "non_intersection",
+            DistanceMetric::Bhattacharyya => "bhattacharyya",
+            DistanceMetric::KullbrackLeibler => "kullbrack_leibler",
+            DistanceMetric::KullbrackLeiblerReverse => "kullbrack_leibler_reverse",
+            DistanceMetric::SymmetricKullbrackLeibler => "symmetric_kullbrack_leibler",
+            DistanceMetric::TotalVariation => "total_variation",
+            DistanceMetric::JeffriesMatusita => "jeffries_matusita",
+            DistanceMetric::ChiSquared => "chi_quared",
+            DistanceMetric::JensenShannon => "jensen_shannon",
+            DistanceMetric::Hellinger => "hellinger",
+            DistanceMetric::Canberra => "canberra",
+            DistanceMetric::BrayCurtis => "bray_curtis",
+            DistanceMetric::Wasserstein => "wasserstein",
+        };
+        write!(f, "{}", name)
+    }
 }
 
-pub fn manhattan_dist<T, U>(target_vec: Vec<T>, ref_vec: Vec<U>) -> f64
-where
-    T: Clone + std::cmp::PartialEq + std::fmt::Debug + ToPrimitive,
-    U: Clone + std::cmp::PartialEq + std::fmt::Debug + ToPrimitive,
-{
-    let target_matrix = maths::vec_to_matrix(target_vec, true);
-    let ref_matrix = maths::vec_to_matrix(ref_vec, true);
-    let dist = target_matrix.apply_metric_distance(&ref_matrix, &LpNorm(1));
-    dist
+/// The normalization of the histograms a metric is defined on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Normalization {
+    /// The raw (weighted) counts.
+    None,
+    /// Unit Euclidean norm.
+    L2,
+    /// Unit sum, i.e. probability distributions.
+    L1,
+}
+
+impl DistanceMetric {
+    /// Returns true if the metric relies on the order of the bins and is thus only meaningful for the
+    /// hour and day histograms.
+    pub fn is_circular(&self) -> bool {
+        matches!(self, DistanceMetric::Wasserstein)
+    }
+
+    /// Returns the normalization the metric requires before the histograms are compared.
+    pub fn normalization(&self) -> Normalization {
+        match self {
+            DistanceMetric::Euclidean | DistanceMetric::Manhattan => Normalization::L2,
+            // Cosine is invariant to the scale of the histograms
+            DistanceMetric::Cosine => Normalization::None,
+            // Canberra and Bray-Curtis compare the bins as distributions, such that traces of a different
+            // length but with the same proportions are equal
+            DistanceMetric::Canberra
+            | DistanceMetric::BrayCurtis
+            | DistanceMetric::NonIntersection
+            | DistanceMetric::Bhattacharyya
+            | DistanceMetric::KullbrackLeibler
+            | DistanceMetric::KullbrackLeiblerReverse
+            | DistanceMetric::SymmetricKullbrackLeibler
+            | DistanceMetric::TotalVariation
+            | DistanceMetric::JeffriesMatusita
+            | DistanceMetric::ChiSquared
+            | DistanceMetric::JensenShannon
+            | DistanceMetric::Hellinger
+            | DistanceMetric::Wasserstein => Normalization::L1,
+        }
+    }
+}
+
+fn normalize(vec: &SparseVector, normalization: Normalization) -> Cow<'_, SparseVector> {
+    match normalization {
+        Normalization::None => Cow::Borrowed(vec),
+        Normalization::L2 => Cow::Owned(vec.normalize()),
+        Normalization::L1 => Cow::Owned(vec.to_distribution()),
+    }
+}
+
+/// Computes the distance between the target and the reference histogram with the given metric, after
+/// normalizing both histograms as the metric requires.
+pub fn dist(metric: DistanceMetric, target_vec: &SparseVector, ref_vec: &SparseVector) -> f64 {
+    let target_vec = &normalize(target_vec, metric.normalization());
+    let ref_vec = &normalize(ref_vec, metric.normalization());
+    match metric {
+        DistanceMetric::Euclidean => euclidean_dist(target_vec, ref_vec),
+        DistanceMetric::Manhattan => manhattan_dist(target_vec, ref_vec),
+        DistanceMetric::Cosine => consine_dist(target_vec, ref_vec),
+        DistanceMetric::NonIntersection => non_intersection_dist(target_vec, ref_vec),
+        DistanceMetric::Bhattacharyya => bhattacharyya_dist(target_vec, ref_vec),
+        DistanceMetric::KullbrackLeibler => kullbrack_leibler_dist(target_vec, ref_vec),
+        DistanceMetric::KullbrackLeiblerReverse => kullbrack_leibler_dist(ref_vec, target_vec),
+        DistanceMetric::SymmetricKullbrackLeibler => symmetric_kullbrack_leibler_dist(target_vec, ref_vec),
+        DistanceMetric::TotalVariation => total_variation_dist(target_vec, ref_vec),
+        DistanceMetric::JeffriesMatusita => jeffries_dist(target_vec, ref_vec),
+        DistanceMetric::ChiSquared => chi_squared_dist(target_vec, ref_vec),
+        DistanceMetric::JensenShannon => jensen_shannon_dist(target_vec, ref_vec),
+        DistanceMetric::Hellinger => hellinger_dist(target_vec, ref_vec),
+        DistanceMetric::Canberra => canberra_dist(target_vec, ref_vec),
+        DistanceMetric::BrayCurtis => bray_curtis_dist(target_vec, ref_vec),
+        DistanceMetric::Wasserstein => wasserstein_dist(target_vec, ref_vec),
+    }
+}
+
+// The distance functions below expect histograms that are already normalized as given by
+// `DistanceMetric::normalization`, see `dist`.
+
+/// Euclidean distance between histograms with unit Euclidean norm.
+pub fn euclidean_dist(target_vec: &SparseVector, ref_vec: &SparseVector) -> f64 {
+    let sq_sum = target_vec.zip_fold(ref_vec, 0.0, |acc, a, b| acc + f64::powi(a - b, 2));
+    sq_sum.sqrt()
 }
 
-pub fn consine_dist<T, U>(target_vec: Vec<T>, ref_vec: Vec<U>) -> f64
-where
-    T: Clone + std::cmp::PartialEq + std::fmt::Debug + ToPrimitive,
-    U: Clone + std::cmp::PartialEq + std::fmt::Debug + ToPrimitive,
-{
-    let target_matrix = maths::vec_to_matrix(target_vec, false);
-    let ref_matrix = maths::vec_to_matrix(ref_vec, false);
-    let norms = target_matrix.norm() * ref_matrix.norm();
+/// Manhattan distance between histograms with unit Euclidean norm.
+pub fn manhattan_dist(target_vec: &SparseVector, ref_vec: &SparseVector) -> f64 {
+    target_vec.zip_fold(ref_vec, 0.0, |acc, a, b| acc + (a - b).abs())
+}
+
+/// Cosine distance, which does not depend on the scale of the histograms.
+pub fn consine_dist(target_vec: &SparseVector, ref_vec: &SparseVector) -> f64 {
+    let norms = target_vec.norm() * ref_vec.norm();
     if norms > 0. {
-        return 1.0 - target_matrix.dot(&ref_matrix) / (target_matrix.norm() * ref_matrix.norm());
+        return 1.0 - target_vec.dot(ref_vec) / norms;
     }
     1.0
 }
 
-pub fn bhattacharyya_dist<T, U>(target_vec: Vec<T>, ref_vec: Vec<U>) -> f64
-where
-    T: Clone + std::cmp::PartialEq + std::fmt::Debug + ToPrimitive,
-    U: Clone + std::cmp::PartialEq + std::fmt::Debug + ToPrimitive,
-{
-    let target_matrix = maths::vec_to_matrix(target_vec, true);
-    let ref_matrix = maths::vec_to_matrix(ref_vec, true);
-    let dist = target_matrix.zip_fold(&ref_matrix, 0.0, |acc, a, b| {
+/// Bhattacharyya distance between probability distributions, which is infinite for disjoint distributions.
+pub fn bhattacharyya_dist(target_vec: &SparseVector, ref_vec: &SparseVector) -> f64 {
+    let coefficient = target_vec.zip_fold(ref_vec, 0.0, |acc, a, b| {
         let sqrt = (a * b).sqrt();
         acc + sqrt
     });
-    -f64::log(dist, E)
+    // Rounding errors may push the coefficient of identical distributions above one
+    -f64::log(coefficient.min(1.0), E)
 }
 
-pub fn kullbrack_leibler_dist<T, U>(target_vec: Vec<T>, ref_vec: Vec<U>) -> f64
-where
-    T: Clone + std::cmp::PartialEq + std::fmt::Debug + ToPrimitive,
-    U: Clone + std::cmp::PartialEq + std::fmt::Debug + ToPrimitive,
-{
-    let target_matrix = maths::vec_to_matrix(target_vec, true);
-    let ref_matrix = maths::vec_to_matrix(ref_vec, true);
+/// Kullback-Leibler divergence of the reference from the target distribution, i.e. KL(target || ref).
+/// A small epsilon is added to every probability, such that the divergence stays finite.
+pub fn kullbrack_leibler_dist(target_vec: &SparseVector, ref_vec: &SparseVector) -> f64 {
     let eps = f64::EPSILON;
-    let dist = target_matrix.zip_fold(&ref_matrix, 0.0, |acc, a, b| {
+    let dist = target_vec.zip_fold(ref_vec, 0.0, |acc, a, b| {
         let a = a + eps;
         let b = b + eps;
         let mul = a * f64::log(b / a, E);
@@ -103,14 +194,14 @@ where
     -dist
 }
 
-pub fn total_variation_dist<T, U>(target_vec: Vec<T>, ref_vec: Vec<U>) -> f64
-where
-    T: Clone + std::cmp::PartialEq + std::fmt::Debug + ToPrimitive,
-    U: Clone + std::cmp::PartialEq + std::fmt::Debug + ToPrimitive,
-{
-    let target_matrix = maths::vec_to_matrix(target_vec, true);
-    let ref_matrix = maths::vec_to_matrix(ref_vec, true);
-    let sum = target_matrix.zip_fold(&ref_matrix, 0.0, |acc, a, b| {
+/// Symmetric Kullback-Leibler divergence, i.e. the sum of the divergences in both directions.
+pub fn symmetric_kullbrack_leibler_dist(target_vec: &SparseVector, ref_vec: &SparseVector) -> f64 {
+    kullbrack_leibler_dist(target_vec, ref_vec) + kullbrack_leibler_dist(ref_vec, target_vec)
+}
+
+/// Total variation distance between probability distributions, bounded by one.
+pub fn total_variation_dist(target_vec: &SparseVector, ref_vec: &SparseVector) -> f64 {
+    let sum = target_vec.zip_fold(ref_vec, 0.0, |acc, a, b| {
         let diff = (a - b).abs();
         acc + diff
     });
@@ -118,14 +209,9 @@ where
     dist
 }
 
-pub fn jeffries_dist<T, U>(target_vec: Vec<T>, ref_vec: Vec<U>) -> f64
-where
-    T: Clone + std::cmp::PartialEq + std::fmt::Debug + ToPrimitive,
-    U: Clone + std::cmp::PartialEq + std::fmt::Debug + ToPrimitive,
-{
-    let target_matrix = maths::vec_to_matrix(target_vec, true);
-    let ref_matrix = maths::vec_to_matrix(ref_vec, true);
-    let sq_sum = target_matrix.zip_fold(&ref_matrix, 0.0, |acc, a, b| {
+/// Jeffries-Matusita distance between probability distributions, bounded by the square root of two.
+pub fn jeffries_dist(target_vec: &SparseVector, ref_vec: &SparseVector) -> f64 {
+    let sq_sum = target_vec.zip_fold(ref_vec, 0.0, |acc, a, b| {
         let diff = a.sqrt() - b.sqrt();
         let sq = f64::powi(diff, 2);
         acc + sq
@@ -134,15 +220,10 @@ where
     dist
 }
 
-pub fn chi_squared_dist<T, U>(target_vec: Vec<T>, ref_vec: Vec<U>) -> f64
-where
-    T: Clone + std::cmp::PartialEq + std::fmt::Debug + ToPrimitive,
-    U: Clone + std::cmp::PartialEq + std::fmt::Debug + ToPrimitive,
-{
-    let target_matrix = maths::vec_to_matrix(target_vec, true);
-    let ref_matrix = maths::vec_to_matrix(ref_vec, true);
+/// Chi-squared distance between probability distributions, bounded by one.
+pub fn chi_squared_dist(target_vec: &SparseVector, ref_vec: &SparseVector) -> f64 {
     let eps = f64::EPSILON;
-    let sum = target_matrix.zip_fold(&ref_matrix, 0.0, |acc, a, b| {
+    let sum = target_vec.zip_fold(ref_vec, 0.0, |acc, a, b| {
         let a = a + eps;
         let b = b + eps;
         let dff_counter = f64::powi(a - b, 2);
@@ -153,18 +234,264 @@ where
     dist
 }
 
-pub fn non_intersection_dist<T, U>(target_vec: Vec<T>, ref_vec: Vec<U>) -> f64
-where
-    T: Clone + std::cmp::PartialEq + std::fmt::Debug + ToPrimitive,
-    U: Clone + std::cmp::PartialEq + std::fmt::Debug + ToPrimitive,
-{
-    let n = target_vec.len() as f64;
-    let target_matrix = maths::vec_to_matrix(target_vec, true);
-    let ref_matrix = maths::vec_to_matrix(ref_vec, true);
-    let sum = target_matrix.zip_fold(&ref_matrix, 0.0, |acc, a, b| {    
+/// Non-intersection distance between probability distributions, i.e. one minus the histogram intersection.
+pub fn non_intersection_dist(target_vec: &SparseVector, ref_vec: &SparseVector) -> f64 {
+    let sum = target_vec.zip_fold(ref_vec, 0.0, |acc, a, b| {
         let min = f64::min(a, b);
         acc + min
     });
-    let dist: f64 = n - sum;
+    let dist: f64 = 1.0 - sum;
     dist
 }
+
+/// Jensen-Shannon divergence (natural logarithm) between probability distributions,
+/// which is symmetric and bounded by ln(2).
+pub fn jensen_shannon_dist(target_vec: &SparseVector, ref_vec: &SparseVector) -> f64 {
+    let sum = target_vec.zip_fold(ref_vec, 0.0, |acc, a, b| {
+        let m = (a + b) * 0.5;
+        let kl_a = if a > 0.0 { a * f64::ln(a / m) } else { 0.0 };
+        let kl_b = if b > 0.0 { b * f64::ln(b / m) } else { 0.0 };
+        acc + kl_a + kl_b
+    });
+    sum * 0.5
+}
+
+/// Hellinger distance between probability distributions, bounded by one.
+pub fn hellinger_dist(target_vec: &SparseVector, ref_vec: &SparseVector) -> f64 {
+    let sq_sum = target_vec.zip_fold(ref_vec, 0.0, |acc, a, b| {
+        acc + f64::powi(a.sqrt() - b.sqrt(), 2)
+    });
+    (sq_sum * 0.5).sqrt()
+}
+
+/// Canberra distance between probability distributions. Bins that are empty in both histograms are skipped.
+pub fn canberra_dist(target_vec: &SparseVector, ref_vec: &SparseVector) -> f64 {
+    target_vec.zip_fold(ref_vec, 0.0, |acc, a, b| {
+        let denominator = a.abs() + b.abs();
+        if denominator > 0.0 {
+            acc + (a - b).abs() / denominator
+        } else {
+            acc
+        }
+    })
+}
+
+/// Bray-Curtis dissimilarity between probability distributions, bounded by one.
+pub fn bray_curtis_dist(target_vec: &SparseVector, ref_vec: &SparseVector) -> f64 {
+    let (diff_sum, sum) = (
+        target_vec.zip_fold(ref_vec, 0.0, |acc, a, b| acc + (a - b).abs()),
+        target_vec.zip_fold(ref_vec, 0.0, |acc, a, b| acc + a + b),
+    );
+    if sum > 0.0 {
+        return diff_sum / sum;
+    }
+    0.0
+}
+
+/// Earth mover's distance between probability distributions whose bins lie on a circle,
+/// e.g. the last hour of the day is adjacent to the first one. The distance is measured in bins.
+///
+/// On a circle, the distance is the sum of the absolute cumulative differences after subtracting their median.
+pub fn wasserstein_dist(target_vec: &SparseVector, ref_vec: &SparseVector) -> f64 {
+    let target_vec = target_vec.to_dense();
+    let ref_vec = ref_vec.to_dense();
+    let mut cum_diff = 0.0;
+    let cum_diff_list: Vec<f64> = target_vec
+        .iter()
+        .zip(ref_vec.iter())
+        .map(|(a, b)| {
+            cum_diff += a - b;
+            cum_diff
+        })
+        .collect();
+    let mut sorted_list = cum_diff_list.clone();
+    sorted_list.sort_by(|a, b| a.total_cmp(b));
+    let Some(median) = sorted_list.get(sorted_list.len() / 2) else {
+        return 0.0;
+    };
+    cum_diff_list.iter().map(|value| (value - median).abs()).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    const EPS: f64 = 1e-9;
+
+    fn vector(values: &[f64]) -> SparseVector {
+        SparseVector {
+            dim: values.len(),
+            entries: values
+                .iter()
+                .enumerate()
+                .filter(|(_, value)| **value != 0.0)
+                .map(|(idx, value)| (idx as u32, *value))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn jensen_shannon_matches_known_values() {
+        let p = vector(&[1.0, 0.0]);
+        let q = vector(&[0.0, 1.0]);
+        assert!(dist(DistanceMetric::JensenShannon, &p, &p).abs() < EPS);
+        assert!((dist(DistanceMetric::JensenShannon, &p, &q) - f64::ln(2.0)).abs() < EPS);
+        // M = [0.75, 0.25]: 0.5 * (0.5 ln(2/3) + 0.5 ln(2)) + 0.5 * ln(4/3)
+        let r = vector(&[1.0, 1.0]);
+        let expected = 0.5 * (0.5 * f64::ln(0.5 / 0.75) + 0.5 * f64::ln(0.5 / 0.25)) + 0.5 * f64::ln(1.0 / 0.75);
+        assert!((dist(DistanceMetric::JensenShannon, &r, &p) - expected).abs() < EPS);
+        assert!((dist(DistanceMetric::JensenShannon, &p, &r) - expected).abs() < EPS);
+    }
+
+    #[test]
+    fn hellinger_matches_known_values() {
+        let p = vector(&[1.0, 0.0]);
+        let q = vector(&[0.0, 3.0]);
+        let r = vector(&[2.0, 2.0]);
+        assert!(dist(DistanceMetric::Hellinger, &p, &p).abs() < EPS);
+        assert!((dist(DistanceMetric::Hellinger, &p, &q) - 1.0).abs() < EPS);
+        // The Bhattacharyya coefficient of [1, 0] and [0.5, 0.5] is sqrt(0.5)
+        assert!((dist(DistanceMetric::Hellinger, &p, &r) - (1.0 - f64::sqrt(0.5)).sqrt()).abs() < EPS);
+    }
+
+    #[test]
+    fn canberra_matches_known_values() {
+        assert!((dist(DistanceMetric::Canberra, &vector(&[1.0, 3.0]), &vector(&[3.0, 1.0])) - 1.0).abs() < EPS);
+        assert!((dist(DistanceMetric::Canberra, &vector(&[2.0, 6.0]), &vector(&[3.0, 1.0])) - 1.0).abs() < EPS);
+        assert!((dist(DistanceMetric::Canberra, &vector(&[1.0, 0.0]), &vector(&[0.0, 1.0])) - 2.0).abs() < EPS);
+        assert!(dist(DistanceMetric::Canberra, &vector(&[0.0, 4.0]), &vector(&[0.0, 4.0])).abs() < EPS);
+    }
+
+    #[test]
+    fn bray_curtis_matches_known_values() {
+        let p = vector(&[1.0, 2.0, 3.0]);
+        let q = vector(&[3.0, 2.0, 1.0]);
+        assert!((dist(DistanceMetric::BrayCurtis, &p, &q) - 1.0 / 3.0).abs() < EPS);
+        assert!((dist(DistanceMetric::BrayCurtis, &vector(&[1.0, 0.0]), &vector(&[0.0, 1.0])) - 1.0).abs() < EPS);
+        assert!((dist(DistanceMetric::BrayCurtis, &vector(&[2.0, 4.0, 6.0]), &q) - 1.0 / 3.0).abs() < EPS);
+        assert!(dist(DistanceMetric::BrayCurtis, &p, &p).abs() < EPS);
+    }
+
+    #[test]
+    fn wasserstein_wraps_around_the_circle() {
+        let emd = |from: u32, to: u32, dim: usize| {
+            dist(DistanceMetric::Wasserstein, &SparseVector::one_hot(from, dim), &SparseVector::one_hot(to, dim))
+        };
+        assert!(emd(3, 3, 24).abs() < EPS);
+        assert!((emd(0, 6, 24) - 6.0).abs() < EPS);
+        assert!((emd(0, 12, 24) - 12.0).abs() < EPS);
+        assert!((emd(0, 23, 24) - 1.0).abs() < EPS);
+        assert!((emd(6, 0, 7) - 1.0).abs() < EPS);
+        // Half of the mass moves one bin in either direction
+        let p = vector(&[0.5, 0.0, 0.5, 0.0, 0.0, 0.0, 0.0]);
+        assert!((dist(DistanceMetric::Wasserstein, &p, &SparseVector::one_hot(1, 7)) - 1.0).abs() < EPS);
+    }
+
+    const ALL_METRICS: [DistanceMetric; 16] = [
+        DistanceMetric::Euclidean,
+        DistanceMetric::Manhattan,
+        DistanceMetric::Cosine,
+        DistanceMetric::NonIntersection,
+        DistanceMetric::Bhattacharyya,
+        DistanceMetric::KullbrackLeibler,
+        DistanceMetric::KullbrackLeiblerReverse,
+        DistanceMetric::SymmetricKullbrackLeibler,
+        DistanceMetric::TotalVariation,
+        DistanceMetric::JeffriesMatusita,
+        DistanceMetric::ChiSquared,
+        DistanceMetric::JensenShannon,
+        DistanceMetric::Hellinger,
+        DistanceMetric::Canberra,
+        DistanceMetric::BrayCurtis,
+        DistanceMetric::Wasserstein,
+    ];
+
+    /// Generates random pairs of sparse histograms with 24 bins, including histograms with disjoint support.
+    fn gen_histogram_pairs() -> Vec<(SparseVector, SparseVector)> {
+        let mut rng = StdRng::seed_from_u64(0);
+        let gen_histogram = |rng: &mut StdRng| {
+            let values: Vec<f64> = (0..24)
+                .map(|_| if rng.gen_bool(0.4) { f64::from(rng.gen_range(1..20u32)) } else { 0.0 })
+                .collect();
+            vector(&values)
+        };
+        let mut pair_list: Vec<(SparseVector, SparseVector)> =
+            (0..200).map(|_| (gen_histogram(&mut rng), gen_histogram(&mut rng))).collect();
+        pair_list.push((vector(&[3.0, 0.0, 0.0, 1.0]), vector(&[0.0, 2.0, 5.0, 0.0])));
+        pair_list
+    }
+
+    #[test]
+    fn metrics_are_zero_for_identical_histograms() {
+        for (p, _) in gen_histogram_pairs().iter().filter(|(p, _)| !p.entries.is_empty()) {
+            // Scaling the histogram must not change the distributions it is compared as
+            let scaled = SparseVector {
+                dim: p.dim,
+                entries: p.entries.iter().map(|(idx, value)| (*idx, value * 3.0)).collect(),
+            };
+            for metric in ALL_METRICS.iter() {
+                assert!(dist(*metric, p, p).abs() < EPS, "{} of {:?} with itself", metric, p);
+                if metric.normalization() != Normalization::None {
+                    assert!(dist(*metric, p, &scaled).abs() < EPS, "{} of {:?} with its multiple", metric, p);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn metrics_are_non_negative() {
+        for (p, q) in gen_histogram_pairs().iter() {
+            for metric in ALL_METRICS.iter() {
+                assert!(dist(*metric, p, q) >= -EPS, "{} of {:?} and {:?}", metric, p, q);
+            }
+        }
+    }
+
+    #[test]
+    fn symmetric_metrics_are_symmetric() {
+        let asymmetric = [DistanceMetric::KullbrackLeibler, DistanceMetric::KullbrackLeiblerReverse];
+        for (p, q) in gen_histogram_pairs().iter() {
+            for metric in ALL_METRICS.iter().filter(|metric| !asymmetric.contains(metric)) {
+                let (d_pq, d_qp) = (dist(*metric, p, q), dist(*metric, q, p));
+                // The Bhattacharyya distance of disjoint histograms is infinite in both directions
+                assert!(
+                    (d_pq - d_qp).abs() < EPS || d_pq == d_qp,
+                    "{} of {:?} and {:?}: {} != {}",
+                    metric,
+                    p,
+                    q,
+                    d_pq,
+                    d_qp
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn kullbrack_leibler_directions_match() {
+        for (p, q) in gen_histogram_pairs().iter() {
+            let forward = dist(DistanceMetric::KullbrackLeibler, p, q);
+            let reverse = dist(DistanceMetric::KullbrackLeiblerReverse, p, q);
+            assert!((dist(DistanceMetric::KullbrackLeibler, q, p) - reverse).abs() < EPS);
+            assert!((dist(DistanceMetric::SymmetricKullbrackLeibler, p, q) - (forward + reverse)).abs() < EPS);
+        }
+    }
+
+    #[test]
+    fn bounded_metrics_match_known_values() {
+        let p = vector(&[1.0, 0.0]);
+        let q = vector(&[0.0, 3.0]);
+        let r = vector(&[2.0, 2.0]);
+        assert!((dist(DistanceMetric::NonIntersection, &p, &q) - 1.0).abs() < EPS);
+        assert!((dist(DistanceMetric::NonIntersection, &p, &r) - 0.5).abs() < EPS);
+        assert!((dist(DistanceMetric::TotalVariation, &p, &q) - 1.0).abs() < EPS);
+        assert!((dist(DistanceMetric::TotalVariation, &p, &r) - 0.5).abs() < EPS);
+        assert!((dist(DistanceMetric::JeffriesMatusita, &p, &q) - f64::sqrt(2.0)).abs() < EPS);
+        assert!((dist(DistanceMetric::ChiSquared, &p, &q) - 1.0).abs() < EPS);
+        assert!((dist(DistanceMetric::Bhattacharyya, &p, &r) - -f64::ln(f64::sqrt(0.5))).abs() < EPS);
+        assert!(dist(DistanceMetric::Bhattacharyya, &p, &q).is_infinite());
+        // KL([1, 0] || [0.5, 0.5]) = ln(2)
+        assert!((dist(DistanceMetric::KullbrackLeibler, &p, &r) - f64::ln(2.0)).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,269 @@
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, collections::HashMap};
+
+/// A histogram over a vocabulary, stored as the non-zero entries sorted by their index in the vocabulary.
+///
+/// The dimension is the size of the vocabulary, such that traces with different values can be compared
+/// without building a common vocabulary per comparison.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SparseVector {
+    pub dim: usize,
+    pub entries: Vec<(u32, f64)>,
+}
+
+impl SparseVector {
+    /// Creates a vector from the counts per index of the vocabulary.
+    pub fn from_counts(counts: HashMap<u32, u32>, dim: usize) -> SparseVector {
+        let mut entries: Vec<(u32, f64)> = counts
+            .into_iter()
+            .filter(|(_, count)| *count > 0)
+            .map(|(idx, count)| (idx, f64::from(count)))
+            .collect();
+        entries.sort_unstable_by_key(|(idx, _)| *idx);
+        SparseVector { dim, entries }
+    }
+
+    /// Creates a vector from a dense vector of counts.
+    pub fn from_dense(counts: &[u32]) -> SparseVector {
+        SparseVector {
+            dim: counts.len(),
+            entries: counts
+                .iter()
+                .enumerate()
+                .filter(|(_, count)| **count > 0)
+                .map(|(idx, count)| (u32::try_from(idx).unwrap(), f64::from(*count)))
+                .collect(),
+        }
+    }
+
+    /// Creates a vector with a single entry of one, e.g. for the age or the gender of a user.
+    pub fn one_hot(idx: u32, dim: usize) -> SparseVector {
+        SparseVector {
+            dim,
+            entries: vec![(idx, 1.0)],
+        }
+    }
+
+    /// Computes the element-wise mean of the given vectors, which share the same vocabulary.
+    pub fn mean<'a, I>(vectors: I) -> SparseVector
+    where
+        I: IntoIterator<Item = &'a SparseVector>,
+    {
+        let mut dim = 0;
+        let mut count = 0;
+        let mut entries: Vec<(u32, f64)> = Vec::new();
+        for vector in vectors.into_iter() {
+            dim = dim.max(vector.dim);
+            count += 1;
+            entries.extend(vector.entries.iter().cloned());
+        }
+        // A stable sort keeps the summation order of equal indices fixed
+        entries.sort_by_key(|(idx, _)| *idx);
+        let mut merged: Vec<(u32, f64)> = Vec::with_capacity(entries.len());
+        for (idx, value) in entries.into_iter() {
+            match merged.last_mut() {
+                Some((last_idx, sum)) if *last_idx == idx => *sum += value,
+                _ => merged.push((idx, value)),
+            }
+        }
+        merged.iter_mut().for_each(|(_, sum)| *sum /= count as f64);
+        SparseVector {
+            dim,
+            entries: merged,
+        }
+    }
+
+    /// Returns the Euclidean norm of the vector.
+    pub fn norm(&self) -> f64 {
+        self.entries.iter().map(|(_, value)| value * value).sum::<f64>().sqrt()
+    }
+
+    /// Returns the vector scaled to unit Euclidean norm. A zero vector stays unchanged.
+    pub fn normalize(&self) -> SparseVector {
+        let norm = self.norm();
+        if norm == 0.0 {
+            return self.clone();
+        }
+        SparseVector {
+            dim: self.dim,
+            entries: self.entries.iter().map(|(idx, value)| (*idx, value / norm)).collect(),
+        }
+    }
+
+    /// Returns the vector scaled to sum up to one, i.e. a probability distribution. A zero vector stays unchanged.
+    pub fn to_distribution(&self) -> SparseVector {
+        let sum: f64 = self.entries.iter().map(|(_, value)| value).sum();
+        if sum == 0.0 {
+            return self.clone();
+        }
+        SparseVector {
+            dim: self.dim,
+            entries: self.entries.iter().map(|(idx, value)| (*idx, value / sum)).collect(),
+        }
+    }
+
+    /// Returns the vector with all entries, including the zero ones.
+    pub fn to_dense(&self) -> Vec<f64> {
+        let mut dense = vec![0.0; self.dim];
+        for (idx, value) in self.entries.iter() {
+            dense[*idx as usize] = *value;
+        }
+        dense
+    }
+
+    /// Folds over every index that is non-zero in at least one of both vectors, passing the values of both
+    /// vectors at that index. Indices that are zero in both vectors are skipped.
+    pub fn zip_fold<F>(&self, other: &SparseVector, init: f64, mut f: F) -> f64
+    where
+        F: FnMut(f64, f64, f64) -> f64,
+    {
+        let (mut i, mut j) = (0, 0);
+        let mut acc = init;
+        loop {
+            let (a, b) = match (self.entries.get(i), other.entries.get(j)) {
+                (None, None) => break,
+                (Some(&(_, a)), None) => {
+                    i += 1;
+                    (a, 0.0)
+                }
+                (None, Some(&(_, b))) => {
+                    j += 1;
+                    (0.0, b)
+                }
+                (Some(&(idx_a, a)), Some(&(idx_b, b))) => match idx_a.cmp(&idx_b) {
+                    Ordering::Less => {
+                        i += 1;
+                        (a, 0.0)
+                    }
+                    Ordering::Greater => {
+                        j += 1;
+                        (0.0, b)
+                    }
+                    Ordering::Equal => {
+                        i += 1;
+                        j += 1;
+                        (a, b)
+                    }
+                },
+            };
+            acc = f(acc, a, b);
+        }
+        acc
+    }
+
+    /// Returns the dot product of both vectors.
+    pub fn dot(&self, other: &SparseVector) -> f64 {
+        self.zip_fold(other, 0.0, |acc, a, b| acc + a * b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPS: f64 = 1e-12;
+
+    fn vector(dim: usize, entries: &[(u32, f64)]) -> SparseVector {
+        SparseVector {
+            dim,
+            entries: entries.to_vec(),
+        }
+    }
+
+    /// Collects the pairs of values visited by `zip_fold`.
+    fn zip_pairs(a: &SparseVector, b: &SparseVector) -> Vec<(f64, f64)> {
+        let mut pairs: Vec<(f64, f64)> = Vec::new();
+        a.zip_fold(b, 0.0, |acc, x, y| {
+            pairs.push((x, y));
+            acc
+        });
+        pairs
+    }
+
+    /// The pairs of values of the dense vectors at every index that is non-zero in at least one of them.
+    fn dense_pairs(a: &SparseVector, b: &SparseVector) -> Vec<(f64, f64)> {
+        a.to_dense()
+            .into_iter()
+            .zip(b.to_dense())
+            .filter(|(x, y)| *x != 0.0 || *y != 0.0)
+            .collect()
+    }
+
+    fn gen_vector_pairs() -> Vec<(SparseVector, SparseVector)> {
+        vec![
+            // Disjoint indices
+            (vector(6, &[(0, 1.0), (1, 2.0)]), vector(6, &[(3, 3.0), (5, 4.0)])),
+            (vector(6, &[(3, 3.0), (5, 4.0)]), vector(6, &[(0, 1.0), (1, 2.0)])),
+            // Interleaved indices, some shared
+            (
+                vector(8, &[(0, 1.0), (2, 2.0), (4, 3.0), (7, 0.5)]),
+                vector(8, &[(1, 5.0), (2, 1.0), (5, 2.0), (7, 1.5)]),
+            ),
+            // A tail left in only one vector
+            (vector(8, &[(1, 1.0)]), vector(8, &[(1, 2.0), (3, 1.0), (6, 4.0), (7, 2.0)])),
+            (vector(8, &[(0, 1.0), (5, 2.0), (6, 3.0), (7, 4.0)]), vector(8, &[(0, 2.0)])),
+            // Empty vectors
+            (vector(4, &[]), vector(4, &[(2, 1.0)])),
+            (vector(4, &[(3, 1.0)]), vector(4, &[])),
+            (vector(4, &[]), vector(4, &[])),
+        ]
+    }
+
+    #[test]
+    fn zip_fold_visits_the_union_of_indices_in_order() {
+        for (a, b) in gen_vector_pairs().iter() {
+            assert_eq!(zip_pairs(a, b), dense_pairs(a, b), "{:?} and {:?}", a, b);
+            let dense_dot: f64 = a.to_dense().iter().zip(b.to_dense()).map(|(x, y)| x * y).sum();
+            assert!((a.dot(b) - dense_dot).abs() < EPS);
+            assert_eq!(a.zip_fold(b, 1.5, |acc, _, _| acc + 1.0), 1.5 + dense_pairs(a, b).len() as f64);
+        }
+    }
+
+    #[test]
+    fn from_counts_matches_from_dense() {
+        let counts = HashMap::from([(4, 2), (0, 1), (2, 0), (1, 7)]);
+        let vector = SparseVector::from_counts(counts, 6);
+        assert_eq!(vector.entries, vec![(0, 1.0), (1, 7.0), (4, 2.0)]);
+        assert_eq!(vector, SparseVector::from_dense(&[1, 7, 0, 0, 2, 0]));
+        assert_eq!(vector.to_dense(), vec![1.0, 7.0, 0.0, 0.0, 2.0, 0.0]);
+        assert_eq!(SparseVector::from_counts(HashMap::new(), 3).to_dense(), vec![0.0; 3]);
+        assert_eq!(SparseVector::one_hot(2, 4).to_dense(), vec![0.0, 0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn normalize_and_to_distribution_match_dense() {
+        for (vector, _) in gen_vector_pairs().iter() {
+            let dense = vector.to_dense();
+            let norm = dense.iter().map(|x| x * x).sum::<f64>().sqrt();
+            let sum: f64 = dense.iter().sum();
+            let normalized = vector.normalize().to_dense();
+            let distribution = vector.to_distribution().to_dense();
+            for (idx, value) in dense.iter().enumerate() {
+                let expected_normalized = if norm > 0.0 { value / norm } else { 0.0 };
+                let expected_distribution = if sum > 0.0 { value / sum } else { 0.0 };
+                assert!((normalized[idx] - expected_normalized).abs() < EPS, "{:?}", vector);
+                assert!((distribution[idx] - expected_distribution).abs() < EPS, "{:?}", vector);
+            }
+        }
+    }
+
+    #[test]
+    fn mean_of_vectors_with_different_support_matches_dense() {
+        let vectors = [
+            vector(5, &[(0, 2.0), (3, 4.0)]),
+            vector(5, &[(1, 3.0), (3, 2.0)]),
+            vector(5, &[]),
+            vector(5, &[(4, 6.0)]),
+        ];
+        let mean = SparseVector::mean(vectors.iter());
+        assert_eq!(mean.dim, 5);
+        assert_eq!(mean.entries.iter().map(|(idx, _)| *idx).collect::<Vec<u32>>(), vec![0, 1, 3, 4]);
+        let expected: Vec<f64> = (0..5)
+            .map(|idx| vectors.iter().map(|vector| vector.to_dense()[idx]).sum::<f64>() / vectors.len() as f64)
+            .collect();
+        for (value, expected) in mean.to_dense().iter().zip(expected.iter()) {
+            assert!((value - expected).abs() < EPS);
+        }
+        assert_eq!(SparseVector::mean(std::iter::empty()), SparseVector::default());
+    }
+}
@@ -0,0 +1,246 @@
+use crate::frequency::{sparse::SparseVector, trace::FreqTrace};
+use crate::parse::DataFields;
+use crate::sample::IdxMaps;
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Display,
+    str::FromStr,
+};
+
+/// The traces of every user in the same order as the parsed traces. Only the traces used in a fold are
+/// weighted, the remaining ones are borrowed from the parsed traces.
+pub type WeightedTraces<'a> = BTreeMap<u32, Vec<Cow<'a, FreqTrace>>>;
+
+/// The data fields whose histograms count the visits of individual sites and are thus weighted.
+const WEIGHTED_FIELDS: [DataFields; 3] = [DataFields::Url, DataFields::Domain, DataFields::Category];
+
+const BM25_K1: f64 = 1.2;
+const BM25_B: f64 = 0.75;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Weighting {
+    #[default]
+    Raw,
+    TfIdf,
+    Bm25,
+    Binary,
+    Log,
+}
+
+impl FromStr for Weighting {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Weighting, Self::Err> {
+        match s {
+            "raw" => Ok(Weighting::Raw),
+            "tf_idf" => Ok(Weighting::TfIdf),
+            "bm25" => Ok(Weighting::Bm25),
+            "binary" => Ok(Weighting::Binary),
+            "log" => Ok(Weighting::Log),
+            x => Err(format!("The supplied weighting does not exist: {:?}", x)),
+        }
+    }
+}
+
+impl Display for Weighting {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Weighting::Raw => "raw",
+            Weighting::TfIdf => "tf_idf",
+            Weighting::Bm25 => "bm25",
+            Weighting::Binary => "binary",
+            Weighting::Log => "log",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Statistics of a data field over the observed traces of all candidate users.
+struct Population {
+    num_traces: f64,
+    avg_len: f64,
+    idx_to_doc_freq_map: HashMap<u32, f64>,
+}
+
+impl Population {
+    fn new<'a, I>(vectors: I) -> Population
+    where
+        I: IntoIterator<Item = &'a SparseVector>,
+    {
+        let mut num_traces = 0.0;
+        let mut total_len = 0.0;
+        let mut idx_to_doc_freq_map: HashMap<u32, f64> = HashMap::new();
+        for vector in vectors.into_iter() {
+            num_traces += 1.0;
+            for (idx, count) in vector.entries.iter() {
+                total_len += count;
+                *idx_to_doc_freq_map.entry(*idx).or_insert(0.0) += 1.0;
+            }
+        }
+        Population {
+            num_traces,
+            avg_len: if num_traces > 0.0 { total_len / num_traces } else { 0.0 },
+            idx_to_doc_freq_map,
+        }
+    }
+
+    /// Returns the smoothed inverse document frequency, which stays positive for values that occur in every
+    /// observed trace and is largest for values that are never observed.
+    fn idf(&self, idx: u32) -> f64 {
+        let doc_freq = self.idx_to_doc_freq_map.get(&idx).cloned().unwrap_or(0.0);
+        ((1.0 + self.num_traces) / (1.0 + doc_freq)).ln() + 1.0
+    }
+}
+
+/// Weights the counts of a single histogram.
+fn weight_vector(weighting: Weighting, vector: &SparseVector, population: &Population) -> SparseVector {
+    let len: f64 = vector.entries.iter().map(|(_, count)| count).sum();
+    let entries = vector
+        .entries
+        .iter()
+        .map(|(idx, count)| {
+            let weight = match weighting {
+                Weighting::Raw => *count,
+                Weighting::TfIdf => count * population.idf(*idx),
+                Weighting::Bm25 => {
+                    let norm_len = if population.avg_len > 0.0 { len / population.avg_len } else { 1.0 };
+                    let saturation = count * (BM25_K1 + 1.0)
+                        / (count + BM25_K1 * (1.0 - BM25_B + BM25_B * norm_len));
+                    saturation * population.idf(*idx)
+                }
+                Weighting::Binary => 1.0,
+                Weighting::Log => 1.0 + count.ln(),
+            };
+            (*idx, weight)
+        })
+        .collect();
+    SparseVector {
+        dim: vector.dim,
+        entries,
+    }
+}
+
+/// Weights the url, domain and category histograms of the sampled observed, test and target traces of a
+/// fold before they are compared. The other traces are never compared in the fold and stay unweighted.
+///
+/// The document frequencies and the average histogram length are computed over the observed traces, i.e. the
+/// knowledge of the attacker, such that sites visited by many users contribute less than rare sites.
+pub fn weight_traces<'a>(
+    weighting: Weighting,
+    user_to_freq_map: &'a BTreeMap<u32, Vec<FreqTrace>>,
+    idx_maps: &IdxMaps,
+) -> WeightedTraces<'a> {
+    let borrow_all = |traces: &'a Vec<FreqTrace>| traces.iter().map(Cow::Borrowed).collect();
+    if weighting == Weighting::Raw {
+        return user_to_freq_map.iter().map(|(user, traces)| (*user, borrow_all(traces))).collect();
+    }
+    log::info!("Weighting the histograms with {}", weighting);
+
+    let population_list: Vec<Population> = WEIGHTED_FIELDS
+        .iter()
+        .map(|field| {
+            Population::new(idx_maps.user_to_sample_idx_map.iter().flat_map(|(user, samples_idx)| {
+                let traces = user_to_freq_map.get(user).unwrap();
+                samples_idx.iter().map(move |idx| traces[*idx].field(field))
+            }))
+        })
+        .collect();
+
+    user_to_freq_map
+        .iter()
+        .map(|(user, traces)| {
+            let used_idx: HashSet<usize> = idx_maps
+                .user_to_sample_idx_map
+                .get(user)
+                .into_iter()
+                .chain(idx_maps.user_to_target_idx_map.get(user))
+                .flatten()
+                .chain(idx_maps.user_to_test_idx_map.get(user))
+                .copied()
+                .collect();
+            if used_idx.is_empty() {
+                return (*user, borrow_all(traces));
+            }
+            let weighted_traces = traces
+                .iter()
+                .enumerate()
+                .map(|(idx, trace)| {
+                    if !used_idx.contains(&idx) {
+                        return Cow::Borrowed(trace);
+                    }
+                    let mut weighted_trace = trace.clone();
+                    for (field, population) in WEIGHTED_FIELDS.iter().zip(population_list.iter()) {
+                        let weighted_vector = weight_vector(weighting, trace.field(field), population);
+                        *weighted_trace.field_mut(field) = weighted_vector;
+                    }
+                    Cow::Owned(weighted_trace)
+                })
+                .collect();
+            (*user, weighted_traces)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPS: f64 = 1e-12;
+
+    fn vector(entries: &[(u32, f64)]) -> SparseVector {
+        SparseVector {
+            dim: 4,
+            entries: entries.to_vec(),
+        }
+    }
+
+    /// Three traces with the lengths 3, 1 and 3, where the site 0 occurs in two traces.
+    fn corpus() -> Vec<SparseVector> {
+        vec![vector(&[(0, 2.0), (1, 1.0)]), vector(&[(0, 1.0)]), vector(&[(2, 3.0)])]
+    }
+
+    #[test]
+    fn idf_is_smoothed() {
+        let population = Population::new(corpus().iter());
+        assert_eq!(population.num_traces, 3.0);
+        assert!((population.avg_len - 7.0 / 3.0).abs() < EPS);
+        assert!((population.idf(0) - (f64::ln(4.0 / 3.0) + 1.0)).abs() < EPS);
+        assert!((population.idf(1) - (f64::ln(2.0) + 1.0)).abs() < EPS);
+        // Sites that are never observed get the largest weight
+        assert!((population.idf(3) - (f64::ln(4.0) + 1.0)).abs() < EPS);
+
+        let empty = Population::new(std::iter::empty());
+        assert_eq!(empty.avg_len, 0.0);
+        assert!((empty.idf(0) - 1.0).abs() < EPS);
+    }
+
+    #[test]
+    fn bm25_saturates_counts() {
+        let corpus = corpus();
+        let population = Population::new(corpus.iter());
+        let weighted = weight_vector(Weighting::Bm25, &corpus[0], &population);
+        // The length 3 is normalized by the average length 7/3: 2 * 2.2 / (2 + 1.2 * (0.25 + 0.75 * 9/7)) = 14/11
+        assert!((weighted.entries[0].1 - 14.0 / 11.0 * population.idf(0)).abs() < EPS);
+        // 1 * 2.2 / (1 + 1.2 * (0.25 + 0.75 * 9/7)) = 77/86
+        assert!((weighted.entries[1].1 - 77.0 / 86.0 * population.idf(1)).abs() < EPS);
+        // The saturated count of a trace with the average length is k1 + 1 times count / (count + k1)
+        let weighted = weight_vector(Weighting::Bm25, &vector(&[(2, 7.0 / 3.0)]), &population);
+        let count = 7.0 / 3.0;
+        let expected = count * (BM25_K1 + 1.0) / (count + BM25_K1) * population.idf(2);
+        assert!((weighted.entries[0].1 - expected).abs() < EPS);
+    }
+
+    #[test]
+    fn simple_weightings_match_known_values() {
+        let corpus = corpus();
+        let population = Population::new(corpus.iter());
+        let tf_idf = weight_vector(Weighting::TfIdf, &corpus[0], &population);
+        assert!((tf_idf.entries[0].1 - 2.0 * population.idf(0)).abs() < EPS);
+        let binary = weight_vector(Weighting::Binary, &corpus[0], &population);
+        assert_eq!(binary.entries, vec![(0, 1.0), (1, 1.0)]);
+        let log = weight_vector(Weighting::Log, &corpus[0], &population);
+        assert!((log.entries[0].1 - (1.0 + f64::ln(2.0))).abs() < EPS);
+        assert!((log.entries[1].1 - 1.0).abs() < EPS);
+    }
+}
@@ -1,125 +1,334 @@
+mod acceptance;
 mod cli;
+mod compare;
+mod decay;
+mod experiment;
+mod field_weights;
 mod frequency;
+mod open_world;
 mod parse;
+mod profile;
 mod sample;
+mod schema;
 mod sequence;
+mod session;
+mod snapshot;
+mod split;
+mod stats;
 mod utils;
 
-use frequency::trace::FreqTrace;
+use cli::{Approach, Config, ConfigError};
+use experiment::Experiment;
+use field_weights::FieldWeights;
+use frequency::{trace::FreqTrace, weighting};
+use parse::{ParsedData, UserIdMap};
+use sample::IdxMaps;
 use sequence::trace::SeqTrace;
 use simple_logger::SimpleLogger;
+use split::{Split, TimedTrace};
+use utils::{AttackResult, UserResult};
 
-use rand::{rngs::StdRng, SeedableRng};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{de::DeserializeOwned, Serialize};
 use std::{
     collections::{BTreeMap, HashMap},
-    fs,
+    error::Error,
 };
 
 fn main() {
-    
     // Load config
-    let config = cli::get_cli_config().unwrap();
+    let config = cli::get_cli_config().unwrap_or_else(|e| e.exit());
 
     // Set up logger
     SimpleLogger::new()
         .with_level(log::LevelFilter::Info)
-        .with_timestamps(true)
+        .with_local_timestamps()
         .init()
         .unwrap();
 
+    // Compare two previous runs instead of running an evaluation
+    if let Some(paths) = &config.compare {
+        compare::compare(&config, &paths[0], &paths[1]).expect("Error comparing the attack logs.");
+        return;
+    }
+
+    // Load the configuration of every run of the experiment or perform a single run. The runs are performed
+    // in the order of the grid, consecutive runs with the same preprocessing share the parsed traces.
+    let configs: Vec<Config> = match &config.experiment {
+        Some(path) => Experiment::from_file(path)
+            .and_then(|experiment| experiment.gen_configs())
+            .expect("Error loading the experiment."),
+        None => vec![config],
+    };
+
+    let mut cache = ParsedCache::default();
+    for (run_idx, config) in configs.iter().enumerate() {
+        if configs.len() > 1 {
+            log::info!("Starting run {} of {}", run_idx + 1, configs.len());
+        }
+        run(config, &mut cache);
+    }
+}
+
+/// Keeps the most recently parsed traces of each approach together with the key of their preprocessing.
+#[derive(Default)]
+struct ParsedCache {
+    sequence: Option<(String, ParsedData<SeqTrace>)>,
+    frequency: Option<(String, ParsedData<FreqTrace>)>,
+}
+
+/// Performs a single evaluation run with the given configuration.
+fn run(config: &Config, cache: &mut ParsedCache) {
+    let columns = parse::read_columns(config).expect("Error reading the dataset.");
+    exit_if_invalid(config.validate_columns(&columns));
+
     // Set random seed for reproducability
     let mut rng = StdRng::seed_from_u64(config.seed);
 
+    if config.dependent {
+        log::info!("Starting the evaluation with dependent linkage attacks");
+    } else {
+        log::info!("Starting the evaluation with independent linkage attacks");
+    }
+
     // Approach 1: Sequence alignment-based
-    if config.approach == "sequence" {
+    if config.approach == Approach::Sequence {
         log::info!("Parsing data for sequence alignment-based approach...");
-        let user_to_seq_map: BTreeMap<u32, Vec<SeqTrace>> =
-            parse::parse_to_sequence(&config).unwrap();
-
-        log::info!("Sampling users...");
-        let user_to_target_idx_map: HashMap<u32, Vec<usize>> =
-            sample::gen_user_to_target_idx_map(
-                &user_to_seq_map,
-                &mut rng,
-                config.user_sample_size,
-                config.target_trace_sample_size,
-            );
+        let (user_to_seq_map, user_id_map) =
+            get_or_parse(&mut cache.sequence, config, Approach::Sequence, parse::parse_to_sequence);
+        write_user_id_map(config, user_id_map);
 
-        log::info!("Sampling traces per user...");
-        let mut user_to_sample_idx_map: HashMap<u32, Vec<usize>> =
-            sample::gen_user_to_sample_idx_map(
-                &user_to_seq_map,
-                &mut rng,
-                config.trace_sample_size,
-            );
+        let idx_maps_list = load_or_sample(config, user_to_seq_map, &mut rng);
+        let mut field_weights_list: Vec<FieldWeights> = Vec::with_capacity(idx_maps_list.len());
+        let user_result_list = eval_folds(user_to_seq_map, &idx_maps_list, |idx_maps| {
+            let field_weights = if config.learn_field_weights {
+                sequence::evaluation::learn_field_weights(
+                    config,
+                    user_to_seq_map,
+                    &idx_maps.user_to_sample_idx_map,
+                )
+            } else {
+                FieldWeights::from_config(config)
+            };
+            let user_result_list = if config.dependent {
+                sequence::evaluation::eval_dependent(
+                    config,
+                    &field_weights,
+                    user_to_seq_map,
+                    &idx_maps.user_to_target_idx_map,
+                    &idx_maps.user_to_sample_idx_map,
+                )
+            } else {
+                sequence::evaluation::eval(
+                    config,
+                    &field_weights,
+                    user_to_seq_map,
+                    &idx_maps.user_to_target_idx_map,
+                    &idx_maps.user_to_sample_idx_map,
+                    &idx_maps.user_to_test_idx_map,
+                )
+            };
+            field_weights_list.push(field_weights);
+            user_result_list
+        });
+        utils::evaluate(config, user_id_map, &user_result_list, &field_weights_list);
 
-        log::info!("Sampling test traces per user...");
-        let user_to_test_idx_map: HashMap<u32, usize> =
-            sample::gen_user_to_test_idx_map(&user_to_sample_idx_map, &mut rng);
-
-        if config.dependent {
-            log::info!("Starting the evaluation with dependent linkage attacks");
-            sequence::evaluation::eval_dependent(
-                &config,
-                &user_to_seq_map,
-                &user_to_target_idx_map,
-                &mut user_to_sample_idx_map,
-            );
-        } else {
-            log::info!("Starting the evaluation with independent linkage attacks");
-            sequence::evaluation::eval(
-                &config,
-                &user_to_seq_map,
-                &user_to_target_idx_map,
-                &user_to_sample_idx_map,
-                &user_to_test_idx_map,
+    // Approach 2: Histogram-based
+    } else {
+        log::info!("Parsing data for histogram-based approach...");
+        let (user_to_freq_map, user_id_map) =
+            get_or_parse(&mut cache.frequency, config, Approach::Frequency, parse::parse_to_frequency);
+        write_user_id_map(config, user_id_map);
+
+        let idx_maps_list = load_or_sample(config, user_to_freq_map, &mut rng);
+        let mut field_weights_list: Vec<FieldWeights> = Vec::with_capacity(idx_maps_list.len());
+        let user_result_list = eval_folds(user_to_freq_map, &idx_maps_list, |idx_maps| {
+            // The weights depend on the observed traces of the fold and are shared by learning and evaluation
+            let user_to_freq_map = &weighting::weight_traces(config.weighting, user_to_freq_map, idx_maps);
+            let field_weights = if config.learn_field_weights {
+                frequency::evaluation::learn_field_weights(
+                    config,
+                    user_to_freq_map,
+                    &idx_maps.user_to_sample_idx_map,
+                )
+            } else {
+                FieldWeights::from_config(config)
+            };
+            let user_result_list = if config.dependent {
+                frequency::evaluation::eval_dependent(
+                    config,
+                    &field_weights,
+                    user_to_freq_map,
+                    &idx_maps.user_to_target_idx_map,
+                    &idx_maps.user_to_sample_idx_map,
+                )
+            } else {
+                frequency::evaluation::eval(
+                    config,
+                    &field_weights,
+                    user_to_freq_map,
+                    &idx_maps.user_to_target_idx_map,
+                    &idx_maps.user_to_sample_idx_map,
+                    &idx_maps.user_to_test_idx_map,
+                )
+            };
+            field_weights_list.push(field_weights);
+            user_result_list
+        });
+        utils::evaluate(config, user_id_map, &user_result_list, &field_weights_list);
+    }
+}
+
+/// Exits like an invalid configuration if problems are found that are only known after loading the configuration.
+fn exit_if_invalid(problems: Vec<String>) {
+    if !problems.is_empty() {
+        ConfigError::Invalid(problems).exit();
+    }
+}
+
+/// Runs the evaluation on the sampled maps of every fold and merges the results per target user, such
+/// that the metrics are aggregated over the linkage attacks of all folds. The time gap of every linkage
+/// attack is determined from the observed traces of its fold.
+fn eval_folds<T, F>(
+    user_to_vector_map: &BTreeMap<u32, Vec<T>>,
+    idx_maps_list: &[IdxMaps],
+    mut eval: F,
+) -> Vec<UserResult>
+where
+    T: TimedTrace,
+    F: FnMut(&IdxMaps) -> Vec<UserResult>,
+{
+    let mut user_to_result_map: BTreeMap<u32, Vec<AttackResult>> = BTreeMap::new();
+    for (fold, idx_maps) in idx_maps_list.iter().enumerate() {
+        let mut fold_result_list = eval(idx_maps);
+        decay::set_gaps(&mut fold_result_list, user_to_vector_map, &idx_maps.user_to_sample_idx_map);
+        if idx_maps_list.len() > 1 {
+            let top_1_list = stats::to_share_list(
+                fold_result_list
+                    .iter()
+                    .flat_map(|(_, result_list)| result_list.iter())
+                    .filter(|result| result.seen)
+                    .map(|result| result.rank == 1),
             );
+            log::info!("Top 1 of fold {} of {}: {}", fold + 1, idx_maps_list.len(), utils::mean(&top_1_list));
+        }
+        for (user_target, result_list) in fold_result_list.into_iter() {
+            user_to_result_map.entry(user_target).or_default().extend(result_list);
         }
+    }
+    user_to_result_map.into_iter().collect()
+}
 
-    // Approach 2: Histogram-based
+/// Returns the cached traces if they were parsed with the same preprocessing. Otherwise, the traces
+/// are parsed (or loaded from a snapshot) and replace the cached ones.
+fn get_or_parse<'a, T, F>(
+    cached: &'a mut Option<(String, ParsedData<T>)>,
+    config: &Config,
+    approach: Approach,
+    parse: F,
+) -> &'a ParsedData<T>
+where
+    T: Serialize + DeserializeOwned,
+    F: FnOnce(&Config) -> Result<ParsedData<T>, Box<dyn Error>>,
+{
+    let key = snapshot::parse_key(config, approach).expect("Error reading the dataset.");
+    if cached.as_ref().is_some_and(|(cached_key, _)| *cached_key == key) {
+        log::info!("Reusing the traces parsed in the previous run");
     } else {
-        log::info!("Parsing data for histogram-based approach...");
-        let user_to_freq_map: BTreeMap<u32, Vec<FreqTrace>> =
-            parse::parse_to_frequency(&config).unwrap();
-
-        log::info!("Sampling a single target trace per user...");
-        let user_to_target_idx_map: HashMap<u32, Vec<usize>> =
-            sample::gen_user_to_target_idx_map(
-                &user_to_freq_map,
-                &mut rng,
-                config.user_sample_size,
+        let parsed_data = snapshot::load_or_parse(config, approach, parse).unwrap();
+        *cached = Some((key, parsed_data));
+    }
+    &cached.as_ref().unwrap().1
+}
+
+/// Writes the original ids of the users to the user id map of the run.
+fn write_user_id_map(config: &Config, user_id_map: &UserIdMap) {
+    user_id_map
+        .write_to_file(&config.run_path(utils::USER_ID_MAP_PATH))
+        .expect("Error writing the user id map.");
+}
+
+/// Samples the users and traces used for the linkage attacks of every fold and stores the sampled indices to file.
+///
+/// The history of every user is split into observed and target traces by the configured splitter. If
+/// configured, the sampled indices are loaded from file instead.
+fn load_or_sample<R: Rng, T: TimedTrace>(
+    config: &Config,
+    user_to_vector_map: &BTreeMap<u32, Vec<T>>,
+    rng: &mut R,
+) -> Vec<IdxMaps> {
+    let splitter = split::from_config(config);
+    let num_folds = splitter.num_folds();
+    if config.load_map {
+        return (0..num_folds)
+            .map(|fold| {
+                let path = split::fold_path(&config.path_to_map, fold, num_folds);
+                log::info!("Loading sampled users and traces from {:?}...", path);
+                let idx_maps = IdxMaps::load(&path).expect("Error loading the sampled maps.");
+                idx_maps
+                    .check(user_to_vector_map)
+                    .expect("The sampled maps do not match the parsed data.");
+                idx_maps
+            })
+            .collect();
+    }
+
+    log::info!("Splitting the histories with {:?}", splitter);
+    let user_to_split_map: BTreeMap<u32, Vec<Split>> = user_to_vector_map
+        .iter()
+        .map(|(user, traces_list)| {
+            let start_times: Vec<f64> = traces_list.iter().map(TimedTrace::start_time).collect();
+            (*user, splitter.split(&start_times, rng))
+        })
+        .collect();
+
+    log::info!("Sampling users...");
+    let target_user_list: Vec<u32> =
+        sample::gen_target_users(&user_to_split_map, rng, config.user_sample_size);
+    // The number of users is only known after parsing, so this cannot be part of the validation of the config
+    exit_if_invalid(config.validate_num_users(target_user_list.len()));
+
+    // Withhold the observed traces of some target users, such that they are not among the candidates
+    let unseen_users: Vec<u32> =
+        sample::gen_unseen_users(&target_user_list, rng, config.unseen_fraction);
+    if !unseen_users.is_empty() {
+        log::info!("Withholding the observed traces of {} target users", unseen_users.len());
+    }
+
+    (0..num_folds)
+        .map(|fold| {
+            log::info!("Sampling traces per user...");
+            let user_to_target_idx_map: HashMap<u32, Vec<usize>> = sample::gen_user_to_target_idx_map(
+                &user_to_split_map,
+                fold,
+                &target_user_list,
+                rng,
                 config.target_trace_sample_size,
             );
-
-        log::info!("Sampling traces per user...");
-        let mut user_to_sample_idx_map: HashMap<u32, Vec<usize>> =
-            sample::gen_user_to_sample_idx_map(
-                &user_to_freq_map,
-                &mut rng,
+            let mut user_to_sample_idx_map: HashMap<u32, Vec<usize>> = sample::gen_user_to_sample_idx_map(
+                &user_to_split_map,
+                fold,
+                rng,
                 config.trace_sample_size,
             );
+            for user in unseen_users.iter() {
+                user_to_sample_idx_map.remove(user);
+            }
 
-        log::info!("Sampling test traces per user...");
-        let user_to_test_idx_map: HashMap<u32, usize> =
-            sample::gen_user_to_test_idx_map(&user_to_sample_idx_map, &mut rng);
-
-        if config.dependent {
-            log::info!("Starting the evaluation with dependent linkage attacks");
-            frequency::evaluation::eval_dependent(
-                &config,
-                &user_to_freq_map,
-                &user_to_target_idx_map,
-                &mut user_to_sample_idx_map,
-            );
-        } else {
-            log::info!("Starting the evaluation with independent linkage attacks");
-            frequency::evaluation::eval(
-                &config,
-                &user_to_freq_map,
-                &user_to_target_idx_map,
-                &user_to_sample_idx_map,
-                &user_to_test_idx_map,
-            );
-        }
-    }
+            log::info!("Sampling test traces per user...");
+            let user_to_test_idx_map: HashMap<u32, usize> =
+                sample::gen_user_to_test_idx_map(&user_to_sample_idx_map, rng);
+
+            let idx_maps = IdxMaps {
+                user_to_target_idx_map,
+                user_to_sample_idx_map,
+                user_to_test_idx_map,
+                unseen_users: unseen_users.clone(),
+            };
+            let path = split::fold_path(&config.path_to_map, fold, num_folds);
+            idx_maps.save(&path).expect("Error writing the sampled maps.");
+            log::info!("Stored sampled users and traces in {:?}", path);
+            idx_maps
+        })
+        .collect()
 }
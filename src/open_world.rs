@@ -0,0 +1,203 @@
+use crate::utils::AttackResult;
+
+use csv::WriterBuilder;
+use serde::Serialize;
+use std::error::Error;
+
+pub const CURVE_PATH: &str = "tmp/open_world_curve";
+
+/// Quality of the answers of the linkage attacks in the open world for a threshold on the score of the best candidate.
+///
+/// An attack answers with its best candidate if the score passes the threshold and with "no match" otherwise.
+/// The answer is correct if the best candidate is the target user. Target users that are not among the
+/// candidates (unseen users) should be answered with "no match".
+#[derive(Debug, Serialize)]
+pub struct OpenWorldPoint {
+    pub threshold: Option<f64>,
+    /// Share of correct matches among all answered matches, 1 if no attack answers with a match.
+    pub precision: f64,
+    /// Share of seen target users that are matched correctly, None without seen target users.
+    pub recall: Option<f64>,
+    /// Share of unseen target users that are matched anyway, None without unseen target users (closed world).
+    pub false_match_rate: Option<f64>,
+    /// Share of seen target users that are not matched correctly, i.e. one minus the recall.
+    pub false_non_match_rate: Option<f64>,
+}
+
+/// Evaluates the answers of the linkage attacks for the given threshold. Without a threshold, every
+/// attack answers with its best candidate.
+pub fn evaluate_threshold(
+    result_list: &[AttackResult],
+    threshold: Option<f64>,
+    higher_is_better: bool,
+) -> OpenWorldPoint {
+    let is_match = |result: &AttackResult| match (result.best_score, threshold) {
+        (None, _) => false,
+        (Some(_), None) => true,
+        (Some(score), Some(threshold)) if higher_is_better => score >= threshold,
+        (Some(score), Some(threshold)) => score <= threshold,
+    };
+
+    let mut num_matches: usize = 0;
+    let mut num_correct_matches: usize = 0;
+    let mut num_false_matches_of_unseen: usize = 0;
+    let mut num_seen: usize = 0;
+    for result in result_list.iter() {
+        if result.seen {
+            num_seen += 1;
+        }
+        if is_match(result) {
+            num_matches += 1;
+            if result.seen && result.rank == 1 {
+                num_correct_matches += 1;
+            } else if !result.seen {
+                num_false_matches_of_unseen += 1;
+            }
+        }
+    }
+    let num_unseen = result_list.len() - num_seen;
+
+    let share = |num: usize, num_total: usize| (num_total > 0).then(|| num as f64 / num_total as f64);
+    let recall = share(num_correct_matches, num_seen);
+    OpenWorldPoint {
+        threshold,
+        precision: if num_matches > 0 {
+            num_correct_matches as f64 / num_matches as f64
+        } else {
+            1.0
+        },
+        recall,
+        false_match_rate: share(num_false_matches_of_unseen, num_unseen),
+        false_non_match_rate: recall.map(|recall| 1.0 - recall),
+    }
+}
+
+/// Evaluates the answers for every threshold at which an answer changes, i.e. the scores of the best
+/// candidates, from the strictest to the most lenient threshold. The points form the ROC curve (recall
+/// over false match rate) as well as the DET curve (false non-match rate over false match rate).
+pub fn gen_curve(result_list: &[AttackResult], higher_is_better: bool) -> Vec<OpenWorldPoint> {
+    let mut threshold_list: Vec<f64> = result_list
+        .iter()
+        .filter_map(|result| result.best_score)
+        .collect();
+    threshold_list.sort_unstable_by(|a, b| a.total_cmp(b));
+    threshold_list.dedup();
+    if higher_is_better {
+        threshold_list.reverse();
+    }
+    threshold_list
+        .into_iter()
+        .map(|threshold| evaluate_threshold(result_list, Some(threshold), higher_is_better))
+        .collect()
+}
+
+/// Writes the points of the curve to the given file.
+pub fn write_curve(path: &str, curve: &[OpenWorldPoint]) -> Result<(), Box<dyn Error>> {
+    let mut wtr = WriterBuilder::new()
+        .delimiter(b',')
+        .has_headers(true)
+        .from_path(path)?;
+    for point in curve.iter() {
+        wtr.serialize(point)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPS: f64 = 1e-12;
+
+    fn result(seen: bool, rank: usize, best_score: Option<f64>) -> AttackResult {
+        AttackResult {
+            target_idx: None,
+            rank,
+            num_candidates: 5,
+            seen,
+            best_score,
+            candidates: Vec::new(),
+            accepted: None,
+            linked_user: None,
+            gap_days: None,
+        }
+    }
+
+    /// Three seen target users, two of which are ranked first, and two unseen ones, one without candidates.
+    /// Distances are the negated similarities.
+    fn gen_result_list(higher_is_better: bool) -> Vec<AttackResult> {
+        let sign = if higher_is_better { 1.0 } else { -1.0 };
+        vec![
+            result(true, 1, Some(sign * 0.9)),
+            result(true, 2, Some(sign * 0.8)),
+            result(false, 6, Some(sign * 0.7)),
+            result(true, 1, Some(sign * 0.4)),
+            result(false, 1, None),
+        ]
+    }
+
+    fn assert_close(actual: Option<f64>, expected: Option<f64>) {
+        match (actual, expected) {
+            (Some(actual), Some(expected)) => assert!((actual - expected).abs() < EPS, "{} != {}", actual, expected),
+            _ => assert_eq!(actual, expected),
+        }
+    }
+
+    fn assert_point(point: &OpenWorldPoint, precision: f64, recall: f64, false_match_rate: f64) {
+        assert_close(Some(point.precision), Some(precision));
+        assert_close(point.recall, Some(recall));
+        assert_close(point.false_match_rate, Some(false_match_rate));
+        assert_close(point.false_non_match_rate, Some(1.0 - recall));
+    }
+
+    #[test]
+    fn threshold_matches_known_values() {
+        for higher_is_better in [true, false] {
+            let sign = if higher_is_better { 1.0 } else { -1.0 };
+            let result_list = gen_result_list(higher_is_better);
+            // Without a threshold, every attack with candidates answers with a match
+            let point = evaluate_threshold(&result_list, None, higher_is_better);
+            assert_eq!(point.threshold, None);
+            assert_point(&point, 0.5, 2.0 / 3.0, 0.5);
+            let point = evaluate_threshold(&result_list, Some(sign * 0.75), higher_is_better);
+            assert_point(&point, 0.5, 1.0 / 3.0, 0.0);
+            // No match at all
+            let point = evaluate_threshold(&result_list, Some(sign * 1.0), higher_is_better);
+            assert_point(&point, 1.0, 0.0, 0.0);
+        }
+    }
+
+    #[test]
+    fn curve_goes_from_the_strictest_to_the_most_lenient_threshold() {
+        for higher_is_better in [true, false] {
+            let sign = if higher_is_better { 1.0 } else { -1.0 };
+            let curve = gen_curve(&gen_result_list(higher_is_better), higher_is_better);
+            let thresholds: Vec<Option<f64>> = curve.iter().map(|point| point.threshold).collect();
+            assert_eq!(thresholds, [0.9, 0.8, 0.7, 0.4].map(|threshold| Some(sign * threshold)));
+            assert_point(&curve[0], 1.0, 1.0 / 3.0, 0.0);
+            assert_point(&curve[1], 0.5, 1.0 / 3.0, 0.0);
+            assert_point(&curve[2], 1.0 / 3.0, 1.0 / 3.0, 0.5);
+            assert_point(&curve[3], 0.5, 2.0 / 3.0, 0.5);
+        }
+    }
+
+    #[test]
+    fn rates_without_denominator_are_none() {
+        // Closed world without unseen target users
+        let seen_result_list: Vec<AttackResult> =
+            gen_result_list(true).into_iter().filter(|result| result.seen).collect();
+        let point = evaluate_threshold(&seen_result_list, None, true);
+        assert_close(point.recall, Some(2.0 / 3.0));
+        assert_eq!(point.false_match_rate, None);
+        assert!(gen_curve(&seen_result_list, true).iter().all(|point| point.false_match_rate.is_none()));
+
+        let unseen_result_list = vec![result(false, 6, Some(0.7))];
+        let point = evaluate_threshold(&unseen_result_list, None, true);
+        assert_eq!((point.precision, point.recall, point.false_non_match_rate), (0.0, None, None));
+        assert_eq!(point.false_match_rate, Some(1.0));
+
+        let point = evaluate_threshold(&[], Some(0.5), true);
+        assert_eq!((point.precision, point.recall, point.false_match_rate), (1.0, None, None));
+    }
+}
@@ -1,30 +1,146 @@
 use crate::cli::Config;
-use crate::frequency::{trace::FreqTrace, maths};
+use crate::frequency::{maths, sparse::SparseVector, trace::FreqTrace};
+use crate::schema::{Column, Schema};
 use crate::sequence::trace::SeqTrace;
+use crate::session;
 
 use chrono::{prelude::DateTime, Datelike, Timelike, Utc};
-use indexmap::IndexSet;
-use serde::Deserialize;
+use indexmap::{IndexMap, IndexSet};
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, HashMap, HashSet},
     convert::TryFrom,
     error::Error,
     fmt::Display,
+    fs::File,
     str::FromStr,
-    string::ParseError,
-    time::{Duration, UNIX_EPOCH},
 };
 
-#[derive(Debug, Deserialize)]
+/// A single browsing event. Columns other than user id, timestamp and url are optional.
+#[derive(Debug)]
 pub struct Record {
     pub user_id: String,
     pub timestamp: f64,
     pub url: String,
-    pub category: String,
-    pub active_seconds: u64,
-    pub domain: String,
-    pub gender: String,
-    pub age: String,
+    pub category: Option<String>,
+    // Not considered by either approach yet, but part of every export we know of
+    #[allow(dead_code)]
+    pub active_seconds: Option<u64>,
+    pub domain: Option<String>,
+    pub gender: Option<String>,
+    pub age: Option<String>,
+}
+
+/// Converts a unix timestamp in seconds, which may be negative, to a date and time in UTC. Timestamps
+/// beyond the range of `chrono` are clamped.
+pub fn to_datetime(timestamp: f64) -> DateTime<Utc> {
+    let millis = (timestamp * 1000.0).floor() as i64;
+    DateTime::from_timestamp_millis(millis).unwrap_or(if millis < 0 {
+        DateTime::<Utc>::MIN_UTC
+    } else {
+        DateTime::<Utc>::MAX_UTC
+    })
+}
+
+/// Streams records from a CSV file whose headers are mapped to the logical columns by a schema.
+pub struct RecordReader {
+    reader: csv::Reader<File>,
+    positions: HashMap<Column, usize>,
+    row: csv::StringRecord,
+}
+
+impl RecordReader {
+    pub fn from_path(path: &str, schema: &Schema) -> Result<RecordReader, Box<dyn Error>> {
+        let mut reader = csv::Reader::from_path(path)?;
+        let headers = reader.headers()?.clone();
+        let mut positions: HashMap<Column, usize> = HashMap::new();
+        for column in Column::ALL.iter() {
+            let header = schema.header(column);
+            match headers.iter().position(|h| h == header) {
+                Some(idx) => {
+                    positions.insert(*column, idx);
+                }
+                None if column.is_required() => {
+                    return Err(format!(
+                        "Required column {} not found in {}, expected header {:?}",
+                        column, path, header
+                    )
+                    .into());
+                }
+                None => log::info!(
+                    "Optional column {} not found in {} (header {:?}), treating it as missing",
+                    column,
+                    path,
+                    header
+                ),
+            }
+        }
+        Ok(RecordReader {
+            reader,
+            positions,
+            row: csv::StringRecord::new(),
+        })
+    }
+
+    /// Returns the columns whose header is found in the dataset.
+    pub fn columns(&self) -> HashSet<Column> {
+        self.positions.keys().copied().collect()
+    }
+
+    fn get(&self, column: Column) -> Option<&str> {
+        self.positions.get(&column).and_then(|idx| self.row.get(*idx))
+    }
+
+    fn get_required(&self, column: Column) -> Result<&str, Box<dyn Error>> {
+        self.get(column).ok_or_else(|| {
+            format!(
+                "Missing value for column {} in line {}",
+                column,
+                self.line()
+            )
+            .into()
+        })
+    }
+
+    fn line(&self) -> u64 {
+        self.row.position().map(|pos| pos.line()).unwrap_or_default()
+    }
+
+    fn to_record(&self) -> Result<Record, Box<dyn Error>> {
+        let timestamp = self
+            .get_required(Column::Timestamp)?
+            .trim()
+            .parse::<f64>()
+            .map_err(|e| format!("Invalid timestamp in line {}: {}", self.line(), e))?;
+        let active_seconds = match self.get(Column::ActiveSeconds).map(str::trim) {
+            Some(value) if !value.is_empty() => Some(value.parse::<u64>().map_err(|e| {
+                format!("Invalid active seconds in line {}: {}", self.line(), e)
+            })?),
+            _ => None,
+        };
+        Ok(Record {
+            user_id: self.get_required(Column::UserId)?.to_string(),
+            timestamp,
+            url: self.get_required(Column::Url)?.to_string(),
+            category: self.get(Column::Category).map(String::from),
+            active_seconds,
+            domain: self.get(Column::Domain).map(String::from),
+            gender: self.get(Column::Gender).map(String::from),
+            age: self.get(Column::Age).map(String::from),
+        })
+    }
+}
+
+impl Iterator for RecordReader {
+    type Item = Result<Record, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.reader.read_record(&mut self.row) {
+            Ok(true) => Some(self.to_record()),
+            Ok(false) => None,
+            Err(e) => Some(Err(e.into())),
+        }
+    }
 }
 
 #[derive(PartialEq, Debug)]
@@ -41,12 +157,27 @@ pub enum DataFields {
 
 impl Display for DataFields {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", self)
+        write!(f, "{:?}", self)
+    }
+}
+
+impl DataFields {
+    /// Returns the column of a record from which the data field is derived.
+    pub fn column(&self) -> Column {
+        match self {
+            DataFields::Url => Column::Url,
+            DataFields::Domain => Column::Domain,
+            DataFields::Gender => Column::Gender,
+            DataFields::Age => Column::Age,
+            DataFields::ActiveSeconds => Column::ActiveSeconds,
+            DataFields::Category => Column::Category,
+            DataFields::Day | DataFields::Hour => Column::Timestamp,
+        }
     }
 }
 
 impl FromStr for DataFields {
-    type Err = ParseError;
+    type Err = String;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_lowercase().as_ref() {
             "url" => Ok(Self::Url),
@@ -57,231 +188,453 @@ impl FromStr for DataFields {
             "hour" => Ok(Self::Hour),
             "gender" => Ok(Self::Gender),
             "active_seconds" => Ok(Self::ActiveSeconds),
-            x => panic!("Error: Wrong data field supplied: {:?}", x),
+            x => Err(format!("Wrong data field supplied: {:?}", x)),
         }
     }
 }
 
-/// Parses the raw data into a convenient tree map for the histogram-based approach.
-pub fn parse_to_frequency(
-    config: &Config,
-) -> Result<BTreeMap<u32, Vec<FreqTrace>>, Box<dyn Error>> {
-    let mut prev_time: f64 = 0.0;
-    let mut prev_user = String::new();
-    let mut trace_len: usize = 0;
-    let mut user_id: u32 = 0;
-
-    let mut user_to_freq_map: BTreeMap<u32, Vec<FreqTrace>> = BTreeMap::new();
-    let mut reader = csv::Reader::from_path(&config.path)?;
-
-    for result in reader.deserialize() {
-        let record: Record = result?;
-
-        if prev_user != record.user_id && !prev_user.is_empty() {
-            // Check last trace added to previous user
-            let prev_traces_list = user_to_freq_map.get_mut(&user_id).unwrap();
-            if !prev_traces_list.is_empty() {
-                if trace_len < config.min_trace_len {
-                    prev_traces_list.pop();
-                }
-            }
-            user_id += 1;
-        }
+/// Parses values per data field of the form `field=value` or `field:value`, separated by whitespace or
+/// commas, into the value of every given data field. Fields without a value are assigned the default.
+pub fn parse_field_values<T>(
+    fields: &[DataFields],
+    assignments: &[String],
+    default: T,
+) -> Result<Vec<T>, String>
+where
+    T: FromStr + Clone,
+    T::Err: Display,
+{
+    let mut values = vec![default; fields.len()];
+    for assignment in assignments.iter().flat_map(|value| value.split(',')).filter(|a| !a.is_empty()) {
+        let (field, value) = assignment
+            .split_once(['=', ':'])
+            .ok_or_else(|| format!("Expected field=value, got: {:?}", assignment))?;
+        let field = DataFields::from_str(field.trim())?;
+        let value = T::from_str(value.trim()).map_err(|e| format!("{:?}: {}", assignment, e))?;
+        let position = fields
+            .iter()
+            .position(|other| *other == field)
+            .ok_or_else(|| format!("The field {} is not among the compared fields", field))?;
+        values[position] = value;
+    }
+    Ok(values)
+}
 
-        if !user_to_freq_map.contains_key(&user_id) {
-            user_to_freq_map.insert(user_id, Vec::with_capacity(10));
-        }
+/// Traces per internal user id together with the mapping to the original user ids.
+pub type ParsedData<T> = (BTreeMap<u32, Vec<T>>, UserIdMap);
 
-        let traces_list = user_to_freq_map.get_mut(&user_id).unwrap();
+/// Bidirectional mapping between the original user ids of the dataset and the internal ids.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct UserIdMap {
+    original_ids: Vec<String>,
+    internal_ids: HashMap<String, u32>,
+}
 
-        if traces_list.is_empty()
-            || trace_len >= config.max_trace_len
-            || record.timestamp - prev_time >= config.delay_limit
-        {
-            if !traces_list.is_empty() {
-                if trace_len < config.min_trace_len
-                    || traces_list.last().unwrap().end_time
-                        - traces_list.last().unwrap().start_time
-                        > config.max_trace_duration
-                {
-                    traces_list.pop();
-                }
-            }
+#[derive(Serialize)]
+struct UserIdRow<'a> {
+    user_id: u32,
+    original_user_id: &'a str,
+}
 
-            let trace = FreqTrace {
-                url: HashMap::new(),
-                domain: HashMap::new(),
-                category: HashMap::new(),
-                hour: maths::zeros_u32(24),
-                day: maths::zeros_u32(7),
-                start_time: record.timestamp,
-                end_time: record.timestamp,
-                age: record.age,
-                gender: record.gender,
-            };
-            traces_list.push(trace);
-            trace_len = 0;
+impl UserIdMap {
+    /// Returns the internal id of the given original id and assigns the next free id to unseen users.
+    pub fn insert(&mut self, original_id: &str) -> u32 {
+        if let Some(user_id) = self.internal_ids.get(original_id) {
+            return *user_id;
         }
+        let user_id = u32::try_from(self.original_ids.len()).unwrap();
+        self.original_ids.push(original_id.to_string());
+        self.internal_ids.insert(original_id.to_string(), user_id);
+        user_id
+    }
 
-        let current_trace = traces_list.last_mut().unwrap();
-
-        // Extract day and hour from unix timestamp
-        let date = UNIX_EPOCH + Duration::from_secs_f64(record.timestamp.clone());
-        let datetime = DateTime::<Utc>::from(date);
-
-        // Convert from u32 to usize
-        let hour_index: usize = usize::try_from(datetime.hour()).unwrap();
-        let day_index: usize = usize::try_from(datetime.weekday().num_days_from_monday()).unwrap();
-
-        current_trace.hour[hour_index] += 1;
-        current_trace.day[day_index] += 1;
-        current_trace.end_time = record.timestamp;
-
-        *current_trace
-            .url
-            .entry(record.url.clone())
-            .or_insert(0) += 1;
-        *current_trace
-            .domain
-            .entry(record.domain.clone())
-            .or_insert(0) += 1;
-        *current_trace
-            .category
-            .entry(record.category.clone())
-            .or_insert(0) += 1;
-
-        prev_time = record.timestamp;
-        prev_user = record.user_id;
-        trace_len += 1;
+    pub fn original(&self, user_id: u32) -> &str {
+        &self.original_ids[user_id as usize]
     }
 
-    // Remove any client with less than the minimum number of traces
-    log::info!(
-        "Numer of users before filtering: {:?}",
-        user_to_freq_map.keys().len()
-    );
-    user_to_freq_map.retain(|_, value| value.len() >= config.min_num_traces);
-    log::info!(
-        "Number of users after filtering: {:?}",
-        user_to_freq_map.keys().len()
-    );
-    let total_num_traces: usize = user_to_freq_map.iter().map(|(_, val)| val.len()).sum();
-    log::info!(
-        "Total number of traces: {:?}",
-        total_num_traces
-    );
-    Ok(user_to_freq_map)
+    /// Writes the mapping to a CSV file with one row per user.
+    pub fn write_to_file(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        let mut wtr = csv::Writer::from_path(path)?;
+        for (user_id, original_user_id) in self.original_ids.iter().enumerate() {
+            wtr.serialize(UserIdRow {
+                user_id: u32::try_from(user_id)?,
+                original_user_id,
+            })?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
 }
 
+/// Summary of how far the input deviated from being grouped by user and sorted by timestamp.
+#[derive(Debug, Default)]
+pub struct OrderReport {
+    pub num_rows: usize,
+    /// The fewest rows that have to be moved to group and sort the input.
+    pub num_reordered: usize,
+    pub num_interleaved_users: usize,
+    pub num_out_of_order: usize,
+}
 
-/// Parses the raw data into a convenient tree map for the sequence aligment-based approach.
-pub fn parse_to_sequence(
-    config: &Config,
-) -> Result<BTreeMap<u32, Vec<SeqTrace>>, Box<dyn Error>> {
-    let mut prev_time: f64 = 0.0;
-    let mut prev_user = String::new();
-    let mut trace_len: usize = 0;
-    let mut user_id: u32 = 0;
+/// Groups the records by user and sorts the records of each user by timestamp.
+///
+/// Users keep the order of their first appearance and records with equal timestamps keep their
+/// relative order, so input that is already grouped and sorted passes through unchanged.
+pub fn order_records(records: Vec<Record>) -> (Vec<Record>, OrderReport) {
+    let mut report = OrderReport {
+        num_rows: records.len(),
+        ..Default::default()
+    };
+    let mut user_to_records_map: IndexMap<String, Vec<(usize, Record)>> = IndexMap::new();
+    let mut interleaved_users: HashSet<String> = HashSet::new();
+    let mut prev_user: Option<String> = None;
+
+    for (position, record) in records.into_iter().enumerate() {
+        let is_new_block = prev_user.as_ref() != Some(&record.user_id);
+        prev_user = Some(record.user_id.clone());
+        let user_records = user_to_records_map
+            .entry(record.user_id.clone())
+            .or_default();
+        if is_new_block && !user_records.is_empty() {
+            interleaved_users.insert(record.user_id.clone());
+        }
+        if user_records
+            .last()
+            .is_some_and(|(_, last)| record.timestamp < last.timestamp)
+        {
+            report.num_out_of_order += 1;
+        }
+        user_records.push((position, record));
+    }
+    report.num_interleaved_users = interleaved_users.len();
+
+    let mut ordered_records: Vec<Record> = Vec::with_capacity(report.num_rows);
+    let mut positions: Vec<usize> = Vec::with_capacity(report.num_rows);
+    for (_, mut user_records) in user_to_records_map.into_iter() {
+        user_records.sort_by(|(_, a), (_, b)| a.timestamp.total_cmp(&b.timestamp));
+        for (position, record) in user_records.into_iter() {
+            positions.push(position);
+            ordered_records.push(record);
+        }
+    }
+    report.num_reordered = report.num_rows - longest_increasing_len(&positions);
+    (ordered_records, report)
+}
+
+/// Returns the length of the longest increasing subsequence, i.e. the largest number of rows that keep
+/// their relative order, such that the remaining rows are the fewest that have to be moved.
+fn longest_increasing_len(positions: &[usize]) -> usize {
+    // The smallest last position of an increasing subsequence of every length
+    let mut tails: Vec<usize> = Vec::new();
+    for position in positions.iter() {
+        let idx = tails.partition_point(|tail| tail < position);
+        if idx == tails.len() {
+            tails.push(*position);
+        } else {
+            tails[idx] = *position;
+        }
+    }
+    tails.len()
+}
+
+/// Reads the headers of the dataset and returns the columns that are found.
+pub fn read_columns(config: &Config) -> Result<HashSet<Column>, Box<dyn Error>> {
+    Ok(RecordReader::from_path(&config.path, &config.schema)?.columns())
+}
 
-    let mut user_to_seq_map: BTreeMap<u32, Vec<SeqTrace>> = BTreeMap::new();
-    let mut reader = csv::Reader::from_path(&config.path)?;
+/// Reads all records of the dataset, grouped by user and ordered by timestamp.
+fn read_ordered_records(config: &Config) -> Result<Vec<Record>, Box<dyn Error>> {
+    let reader = RecordReader::from_path(&config.path, &config.schema)?;
+    let records = reader.collect::<Result<Vec<Record>, Box<dyn Error>>>()?;
+    let (records, report) = order_records(records);
+    if report.num_reordered > 0 {
+        log::warn!(
+            "Reordered {:?} of {:?} rows: {:?} users with interleaved rows, {:?} rows with out-of-order timestamps",
+            report.num_reordered,
+            report.num_rows,
+            report.num_interleaved_users,
+            report.num_out_of_order
+        );
+    } else {
+        log::info!("All {:?} rows are grouped by user and ordered by timestamp", report.num_rows);
+    }
+    Ok(records)
+}
 
+/// Parses the raw data into a convenient tree map for the histogram-based approach.
+///
+/// The histograms are indexed by a global vocabulary per data field, which is built once while parsing,
+/// such that traces can be compared without building a common vocabulary per comparison.
+pub fn parse_to_frequency(config: &Config) -> Result<ParsedData<FreqTrace>, Box<dyn Error>> {
     let mut url_set: IndexSet<String> = IndexSet::new();
     let mut domain_set: IndexSet<String> = IndexSet::new();
     let mut category_set: IndexSet<String> = IndexSet::new();
-
-    for result in reader.deserialize() {
-        let record: Record = result?;
-
-        if prev_user != record.user_id && !prev_user.is_empty() {
-            // Check last mobility trace added to previous user
-            let prev_traces_list = user_to_seq_map.get_mut(&user_id).unwrap();
-            if !prev_traces_list.is_empty() {
-                if trace_len < config.min_trace_len {
-                    prev_traces_list.pop();
-                }
-            }
-            user_id += 1;
+    let mut age_set: IndexSet<String> = IndexSet::new();
+    let mut gender_set: IndexSet<String> = IndexSet::new();
+
+    let (mut user_to_traces_map, user_id_map) = parse_traces(config, |records| {
+        let first_record = records.first().unwrap();
+        let mut url_counts: HashMap<u32, u32> = HashMap::new();
+        let mut domain_counts: HashMap<u32, u32> = HashMap::new();
+        let mut category_counts: HashMap<u32, u32> = HashMap::new();
+        let mut hour_counts = maths::zeros_u32(24);
+        let mut day_counts = maths::zeros_u32(7);
+
+        for record in records.iter() {
+            // Extract day and hour from unix timestamp
+            let datetime = to_datetime(record.timestamp);
+
+            // Convert from u32 to usize
+            let hour_index: usize = usize::try_from(datetime.hour()).unwrap();
+            let day_index: usize =
+                usize::try_from(datetime.weekday().num_days_from_monday()).unwrap();
+
+            hour_counts[hour_index] += 1;
+            day_counts[day_index] += 1;
+
+            let (url_idx, _) = url_set.insert_full(record.url.clone());
+            let (domain_idx, _) = domain_set.insert_full(record.domain.clone().unwrap_or_default());
+            let (category_idx, _) =
+                category_set.insert_full(record.category.clone().unwrap_or_default());
+            *url_counts.entry(u32::try_from(url_idx).unwrap()).or_insert(0) += 1;
+            *domain_counts.entry(u32::try_from(domain_idx).unwrap()).or_insert(0) += 1;
+            *category_counts.entry(u32::try_from(category_idx).unwrap()).or_insert(0) += 1;
         }
-
-        if !user_to_seq_map.contains_key(&user_id) {
-            user_to_seq_map.insert(user_id, Vec::with_capacity(10));
+        let (age_idx, _) = age_set.insert_full(first_record.age.clone().unwrap_or_default());
+        let (gender_idx, _) = gender_set.insert_full(first_record.gender.clone().unwrap_or_default());
+
+        // The dimensions are only known once all traces are parsed
+        FreqTrace {
+            url: SparseVector::from_counts(url_counts, 0),
+            domain: SparseVector::from_counts(domain_counts, 0),
+            category: SparseVector::from_counts(category_counts, 0),
+            age: SparseVector::one_hot(u32::try_from(age_idx).unwrap(), 0),
+            gender: SparseVector::one_hot(u32::try_from(gender_idx).unwrap(), 0),
+            hour: SparseVector::from_dense(&hour_counts),
+            day: SparseVector::from_dense(&day_counts),
+            start_time: first_record.timestamp,
+            end_time: records.last().unwrap().timestamp,
         }
+    })?;
 
-        let traces_list = user_to_seq_map.get_mut(&user_id).unwrap();
+    log::info!(
+        "Vocabulary sizes: {:?} urls, {:?} domains, {:?} categories",
+        url_set.len(),
+        domain_set.len(),
+        category_set.len()
+    );
+    for trace in user_to_traces_map.values_mut().flatten() {
+        trace.url.dim = url_set.len();
+        trace.domain.dim = domain_set.len();
+        trace.category.dim = category_set.len();
+        trace.age.dim = age_set.len();
+        trace.gender.dim = gender_set.len();
+    }
+    Ok((user_to_traces_map, user_id_map))
+}
 
-        if traces_list.is_empty()
-            || trace_len >= config.max_trace_len
-            || record.timestamp - prev_time >= config.delay_limit
-        {
-            if !traces_list.is_empty() {
-                if trace_len < config.min_trace_len
-                    || traces_list.last().unwrap().end_time
-                        - traces_list.last().unwrap().start_time
-                        > config.max_trace_duration
-                {
-                    traces_list.pop();
-                }
-            }
+/// Parses the raw data into a convenient tree map for the sequence aligment-based approach.
+pub fn parse_to_sequence(config: &Config) -> Result<ParsedData<SeqTrace>, Box<dyn Error>> {
+    let mut url_set: IndexSet<String> = IndexSet::new();
+    let mut domain_set: IndexSet<String> = IndexSet::new();
+    let mut category_set: IndexSet<String> = IndexSet::new();
 
-            let trace = SeqTrace {
-                url: Vec::with_capacity(10),
-                domain: Vec::with_capacity(10),
-                category: Vec::with_capacity(10),
-                hour: Vec::with_capacity(10),
-                day: 0,
-                start_time: record.timestamp,
-                end_time: record.timestamp,
-                age: record.age,
-                gender: record.gender,
-            };
-            traces_list.push(trace);
-            trace_len = 0;
+    parse_traces(config, |records| {
+        let first_record = records.first().unwrap();
+        let mut trace = SeqTrace {
+            url: Vec::with_capacity(records.len()),
+            domain: Vec::with_capacity(records.len()),
+            category: Vec::with_capacity(records.len()),
+            hour: Vec::with_capacity(records.len()),
+            day: 0,
+            start_time: first_record.timestamp,
+            end_time: records.last().unwrap().timestamp,
+            age: first_record.age.clone().unwrap_or_default(),
+            gender: first_record.gender.clone().unwrap_or_default(),
+        };
+
+        for record in records.iter() {
+            // Extract day and hour from unix timestamp
+            let datetime = to_datetime(record.timestamp);
+
+            trace.hour.push(datetime.hour());
+            trace.day = datetime.weekday().num_days_from_monday();
+
+            let (url_idx, _) = url_set.insert_full(record.url.clone());
+            let (domain_idx, _) = domain_set.insert_full(record.domain.clone().unwrap_or_default());
+            let (category_idx, _) =
+                category_set.insert_full(record.category.clone().unwrap_or_default());
+            trace.url.push(u32::try_from(url_idx).unwrap());
+            trace.domain.push(u32::try_from(domain_idx).unwrap());
+            trace.category.push(u32::try_from(category_idx).unwrap());
         }
+        trace
+    })
+}
 
-        let current_trace = traces_list.last_mut().unwrap();
-
-        // Extract day and hour from unix timestamp
-        let date = UNIX_EPOCH + Duration::from_secs_f64(record.timestamp.clone());
-        let datetime = DateTime::<Utc>::from(date);
-
-        url_set.insert(record.url.clone());
-        domain_set.insert(record.domain.clone());
-        category_set.insert(record.category.clone());
-
-        current_trace.hour.push(datetime.hour());
-        current_trace.day = datetime.weekday().num_days_from_monday();
-        current_trace.end_time = record.timestamp;
-
-        current_trace
-            .url
-            .push(u32::try_from(url_set.get_full(&record.url).unwrap().0).unwrap());
-        current_trace
-            .domain
-            .push(u32::try_from(domain_set.get_full(&record.domain).unwrap().0).unwrap());
-        current_trace
-            .category
-            .push(u32::try_from(category_set.get_full(&record.category).unwrap().0).unwrap());
-
-        prev_time = record.timestamp;
-        prev_user = record.user_id;
-        trace_len += 1;
+/// Splits the browsing history of every user into traces and builds each trace from its records.
+///
+/// The traces are determined by the configured sessionizer and cut to the maximum trace length.
+/// Traces that are too short or last too long are discarded, as are users that end up with too few traces.
+fn parse_traces<T, F>(config: &Config, mut build_trace: F) -> Result<ParsedData<T>, Box<dyn Error>>
+where
+    F: FnMut(&[Record]) -> T,
+{
+    let records = read_ordered_records(config)?;
+    let sessionizer = session::from_config(config);
+    log::info!("Splitting the browsing histories with {:?}", sessionizer);
+
+    let mut user_id_map = UserIdMap::default();
+    let mut user_to_traces_map: BTreeMap<u32, Vec<T>> = BTreeMap::new();
+
+    for user_records in records.chunk_by(|a, b| a.user_id == b.user_id) {
+        let user_id = user_id_map.insert(&user_records[0].user_id);
+        let sessions = session::cap_length(sessionizer.split(user_records), config.max_trace_len);
+        let traces_list: Vec<T> = sessions
+            .into_iter()
+            .map(|session| &user_records[session])
+            .filter(|trace_records| {
+                let duration =
+                    trace_records.last().unwrap().timestamp - trace_records.first().unwrap().timestamp;
+                trace_records.len() >= config.min_trace_len && duration <= config.max_trace_duration
+            })
+            .map(&mut build_trace)
+            .collect();
+        user_to_traces_map.insert(user_id, traces_list);
     }
 
     // Remove any client with less than the minimum number of traces
     log::info!(
         "Number of users before filtering: {:?}",
-        user_to_seq_map.keys().len()
+        user_to_traces_map.keys().len()
     );
-    user_to_seq_map.retain(|_, value| value.len() >= config.min_num_traces);
+    user_to_traces_map.retain(|_, value| value.len() >= config.min_num_traces);
     log::info!(
         "Number of users after filtering: {:?}",
-        user_to_seq_map.keys().len()
+        user_to_traces_map.keys().len()
     );
-    let total_num_traces: usize = user_to_seq_map.iter().map(|(_, val)| val.len()).sum();
+    let total_num_traces: usize = user_to_traces_map.values().map(|val| val.len()).sum();
     log::info!(
         "Total number of traces: {:?}",
         total_num_traces
     );
-    Ok(user_to_seq_map)
+    Ok((user_to_traces_map, user_id_map))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Writes the content to a CSV file in the temporary directory that is unique per test.
+    fn write_csv(name: &str, content: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}_{}.csv", name, std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn record(user_id: &str, timestamp: f64) -> Record {
+        Record {
+            user_id: user_id.to_string(),
+            timestamp,
+            url: format!("{}/{}", user_id, timestamp),
+            category: None,
+            active_seconds: None,
+            domain: None,
+            gender: None,
+            age: None,
+        }
+    }
+
+    fn order(rows: &[(&str, f64)]) -> (Vec<(String, f64)>, OrderReport) {
+        let (records, report) = order_records(rows.iter().map(|(user, time)| record(user, *time)).collect());
+        let rows = records.into_iter().map(|record| (record.user_id, record.timestamp)).collect();
+        (rows, report)
+    }
+
+    #[test]
+    fn ordered_records_pass_through() {
+        let (rows, report) = order(&[("a", 1.0), ("a", 2.0), ("b", 1.0), ("b", 1.0), ("c", 0.0)]);
+        assert_eq!(
+            rows,
+            vec![("a".into(), 1.0), ("a".into(), 2.0), ("b".into(), 1.0), ("b".into(), 1.0), ("c".into(), 0.0)]
+        );
+        assert_eq!(report.num_rows, 5);
+        assert_eq!(report.num_reordered, 0);
+        assert_eq!(report.num_interleaved_users, 0);
+        assert_eq!(report.num_out_of_order, 0);
+    }
+
+    #[test]
+    fn single_displaced_row_counts_once() {
+        // The last row belongs to the first user, which shifts every row of the other users
+        let (rows, report) = order(&[("a", 1.0), ("b", 1.0), ("b", 2.0), ("c", 1.0), ("c", 2.0), ("a", 3.0)]);
+        assert_eq!(rows[..2], [("a".into(), 1.0), ("a".into(), 3.0)]);
+        assert_eq!(report.num_reordered, 1);
+        assert_eq!(report.num_interleaved_users, 1);
+        assert_eq!(report.num_out_of_order, 0);
+    }
+
+    #[test]
+    fn out_of_order_rows_are_sorted() {
+        let (rows, report) = order(&[("a", 3.0), ("a", 1.0), ("a", 2.0), ("b", 2.0), ("b", 1.0)]);
+        assert_eq!(
+            rows,
+            vec![("a".into(), 1.0), ("a".into(), 2.0), ("a".into(), 3.0), ("b".into(), 1.0), ("b".into(), 2.0)]
+        );
+        assert_eq!(report.num_reordered, 2);
+        assert_eq!(report.num_interleaved_users, 0);
+        assert_eq!(report.num_out_of_order, 2);
+    }
+
+    #[test]
+    fn record_reader_maps_headers_by_schema() {
+        let path = write_csv(
+            "record_reader_schema",
+            "panelist,time,page,domain\nu1,10.5,a.com/x,a.com\nu2,11,b.com,\n",
+        );
+        let mut schema = Schema::default();
+        let pairs = ["user_id=panelist", "timestamp=time", "url=page"].map(String::from);
+        schema.apply_overrides(&pairs).unwrap();
+        let reader = RecordReader::from_path(path.to_str().unwrap(), &schema).unwrap();
+        assert_eq!(
+            reader.columns(),
+            HashSet::from([Column::UserId, Column::Timestamp, Column::Url, Column::Domain])
+        );
+
+        let records: Vec<Record> = reader.collect::<Result<_, _>>().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].user_id, "u1");
+        assert_eq!(records[0].timestamp, 10.5);
+        assert_eq!(records[0].url, "a.com/x");
+        assert_eq!(records[0].domain.as_deref(), Some("a.com"));
+        assert_eq!(records[1].domain.as_deref(), Some(""));
+        // Optional columns that are not found are missing rather than empty
+        assert_eq!(records[0].age, None);
+        assert_eq!(records[0].category, None);
+        assert_eq!(records[0].active_seconds, None);
+    }
+
+    #[test]
+    fn record_reader_rejects_missing_required_columns() {
+        let path = write_csv("record_reader_required", "user_id,url\nu1,a.com\n");
+        let result = RecordReader::from_path(path.to_str().unwrap(), &Schema::default());
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn record_reader_rejects_invalid_values() {
+        let path = write_csv(
+            "record_reader_values",
+            "user_id,timestamp,url,active_seconds\nu1,noon,a.com,3\nu1,12,a.com,-1\nu1,13,a.com,\n",
+        );
+        let reader = RecordReader::from_path(path.to_str().unwrap(), &Schema::default()).unwrap();
+        let results: Vec<Result<Record, Box<dyn Error>>> = reader.collect();
+        std::fs::remove_file(&path).unwrap();
+        assert!(results[0].is_err());
+        assert!(results[1].is_err());
+        assert_eq!(results[2].as_ref().unwrap().active_seconds, None);
+    }
 }
@@ -0,0 +1,134 @@
+use std::collections::{BTreeMap, HashMap};
+
+use rayon::prelude::*;
+
+/// Traces from which a typical trace can be generated that represents the behavior of a user.
+pub trait TypicalTrace: Sized {
+    fn gen_typical(traces: &[&Self]) -> Self;
+}
+
+/// The knowledge of the attacker about a single candidate user.
+#[derive(Debug)]
+pub struct Profile<'a, T> {
+    /// The observed traces of the user and the target traces linked to the user in dependent linkage attacks.
+    pub history: Vec<&'a T>,
+    /// The typical trace of the history, only generated if typical traces are compared.
+    pub typical: Option<T>,
+}
+
+/// The profiles of all candidate users, built once after sampling and queried by every linkage attack.
+///
+/// In dependent linkage attacks, linked target traces are added to the profile of the linked user, such that
+/// only that profile has to be updated between attacks.
+#[derive(Debug)]
+pub struct ProfileStore<'a, T> {
+    user_to_profile_map: BTreeMap<u32, Profile<'a, T>>,
+}
+
+impl<'a, T> ProfileStore<'a, T>
+where
+    T: TypicalTrace + Send + Sync,
+{
+    /// Builds the profiles of all users with sampled observed traces. Users whose observed traces are withheld
+    /// are no candidates and thus have no profile.
+    pub fn new(
+        user_to_traces_map: &'a BTreeMap<u32, Vec<T>>,
+        user_to_sample_idx_map: &HashMap<u32, Vec<usize>>,
+        typical: bool,
+    ) -> ProfileStore<'a, T> {
+        let user_to_profile_map = user_to_traces_map
+            .par_iter()
+            .filter_map(|(user, traces)| {
+                let samples_idx = user_to_sample_idx_map.get(user)?;
+                let history: Vec<&T> = samples_idx.iter().map(|idx| traces.get(*idx).unwrap()).collect();
+                let typical = typical.then(|| T::gen_typical(&history));
+                Some((*user, Profile { history, typical }))
+            })
+            .collect();
+        ProfileStore { user_to_profile_map }
+    }
+
+    /// Returns the profiles of all candidate users, ordered by user.
+    pub fn profiles(&self) -> &BTreeMap<u32, Profile<'a, T>> {
+        &self.user_to_profile_map
+    }
+
+    /// Adds a linked target trace to the history of the given user and regenerates the typical trace of that
+    /// user if required.
+    pub fn link(&mut self, user: u32, trace: &'a T) {
+        let Some(profile) = self.user_to_profile_map.get_mut(&user) else {
+            return;
+        };
+        profile.history.push(trace);
+        if profile.typical.is_some() {
+            profile.typical = Some(T::gen_typical(&profile.history));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Traces are plain numbers whose typical trace is their sum, such that a regenerated typical trace shows.
+    impl TypicalTrace for u32 {
+        fn gen_typical(traces: &[&Self]) -> Self {
+            traces.iter().copied().sum()
+        }
+    }
+
+    fn gen_user_to_traces_map() -> BTreeMap<u32, Vec<u32>> {
+        BTreeMap::from([(0, vec![1, 2, 3]), (1, vec![10, 20]), (2, vec![100, 200])])
+    }
+
+    fn histories(store: &ProfileStore<u32>) -> Vec<(u32, Vec<u32>, Option<u32>)> {
+        store
+            .profiles()
+            .iter()
+            .map(|(user, profile)| (*user, profile.history.iter().copied().copied().collect(), profile.typical))
+            .collect()
+    }
+
+    #[test]
+    fn withheld_users_get_no_profile() {
+        let user_to_traces_map = gen_user_to_traces_map();
+        // User 1 is withheld (unseen), so none of its traces is sampled
+        let user_to_sample_idx_map = HashMap::from([(0, vec![2, 0]), (2, vec![1])]);
+        let store = ProfileStore::new(&user_to_traces_map, &user_to_sample_idx_map, true);
+        assert_eq!(histories(&store), vec![(0, vec![3, 1], Some(4)), (2, vec![200], Some(200))]);
+
+        let store = ProfileStore::new(&user_to_traces_map, &user_to_sample_idx_map, false);
+        assert_eq!(histories(&store), vec![(0, vec![3, 1], None), (2, vec![200], None)]);
+    }
+
+    #[test]
+    fn link_extends_only_the_linked_profile() {
+        let user_to_traces_map = gen_user_to_traces_map();
+        let user_to_sample_idx_map = HashMap::from([(0, vec![0]), (1, vec![0]), (2, vec![0])]);
+        let target = 7;
+        for typical in [true, false] {
+            let mut store = ProfileStore::new(&user_to_traces_map, &user_to_sample_idx_map, typical);
+            store.link(1, &target);
+            let expected_typical = |sum: u32| typical.then_some(sum);
+            assert_eq!(
+                histories(&store),
+                vec![
+                    (0, vec![1], expected_typical(1)),
+                    (1, vec![10, 7], expected_typical(17)),
+                    (2, vec![100], expected_typical(100)),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn link_to_a_missing_user_is_ignored() {
+        let user_to_traces_map = gen_user_to_traces_map();
+        let user_to_sample_idx_map = HashMap::from([(0, vec![0, 1])]);
+        let mut store = ProfileStore::new(&user_to_traces_map, &user_to_sample_idx_map, true);
+        let target = 7;
+        store.link(1, &target);
+        store.link(5, &target);
+        assert_eq!(histories(&store), vec![(0, vec![1, 2], Some(3))]);
+    }
+}
@@ -1,53 +1,158 @@
+use crate::split::Split;
+
 use rand::{prelude::SliceRandom, seq::IteratorRandom, Rng};
-use std::collections::{BTreeMap, HashMap};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fs,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+/// The sampled trace indices that determine which linkage attacks are performed during evaluation.
+///
+/// The maps can be stored to and loaded from a pickle or JSON file, such that both approaches can be
+/// evaluated on exactly the same users and traces.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IdxMaps {
+    pub user_to_target_idx_map: HashMap<u32, Vec<usize>>,
+    pub user_to_sample_idx_map: HashMap<u32, Vec<usize>>,
+    pub user_to_test_idx_map: HashMap<u32, usize>,
+    /// Target users whose observed traces are withheld from the candidates (open-world evaluation).
+    #[serde(default)]
+    pub unseen_users: Vec<u32>,
+}
+
+impl IdxMaps {
+    /// Loads the maps from file. Files ending with `.json` are read as JSON, all others as pickle.
+    pub fn load(path: &str) -> Result<IdxMaps, Box<dyn Error>> {
+        let reader = BufReader::new(fs::File::open(path)?);
+        let idx_maps = if is_json(path) {
+            serde_json::from_reader(reader)?
+        } else {
+            serde_pickle::from_reader(reader, serde_pickle::DeOptions::new())?
+        };
+        Ok(idx_maps)
+    }
+
+    /// Stores the maps to file. Files ending with `.json` are written as JSON, all others as pickle.
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut writer = BufWriter::new(fs::File::create(path)?);
+        if is_json(path) {
+            serde_json::to_writer(&mut writer, self)?;
+        } else {
+            serde_pickle::to_writer(&mut writer, self, serde_pickle::SerOptions::new())?;
+        }
+        Ok(())
+    }
+
+    /// Checks that every sampled user and trace exists in the given data. Maps that were sampled on
+    /// data parsed with a different configuration are likely to fail this check.
+    pub fn check<T>(&self, user_to_vector_map: &BTreeMap<u32, Vec<T>>) -> Result<(), Box<dyn Error>> {
+        let idx_lists = self
+            .user_to_target_idx_map
+            .iter()
+            .chain(self.user_to_sample_idx_map.iter())
+            .map(|(user, idx_list)| (user, idx_list.as_slice()))
+            .chain(
+                self.user_to_test_idx_map
+                    .iter()
+                    .map(|(user, idx)| (user, std::slice::from_ref(idx))),
+            );
+        for (user, idx_list) in idx_lists {
+            let traces_list = user_to_vector_map
+                .get(user)
+                .ok_or_else(|| format!("User {} of the sampled maps does not exist", user))?;
+            if let Some(idx) = idx_list.iter().find(|idx| **idx >= traces_list.len()) {
+                return Err(format!(
+                    "Trace {} of user {} does not exist, the user only has {} traces",
+                    idx,
+                    user,
+                    traces_list.len()
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+}
 
-/// Samples a subset of users and a number of target traces indices.
+fn is_json(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+}
+
+/// Samples a subset of the users whose history provides target traces.
 ///
-/// The number of sampled users corresponds to the number of linkage attacks that will
-/// be performed during evaluation. The sampled target traces are found in the second half of the
-/// cronologically ordered history of each user.
-pub fn gen_user_to_target_idx_map<R: Rng, T>(
-    user_to_vector_map: &BTreeMap<u32, Vec<T>>,
+/// The number of sampled users corresponds to the number of users that are attacked during evaluation.
+pub fn gen_target_users<R: Rng>(
+    user_to_split_map: &BTreeMap<u32, Vec<Split>>,
     rng: &mut R,
     user_sample_size: usize,
+) -> Vec<u32> {
+    // Only users with target traces in every fold can be attacked
+    let user_list: Vec<u32> = user_to_split_map
+        .iter()
+        .filter(|(_, split_list)| split_list.iter().all(|split| !split.target.is_empty()))
+        .map(|(user, _)| *user)
+        .collect();
+    user_list.into_iter().choose_multiple(rng, user_sample_size)
+}
+
+/// Samples a number of target traces indices for each of the given target users.
+///
+/// The sampled target traces are found among the target traces of the given fold, by default in the
+/// second half of the cronologically ordered history of each user.
+pub fn gen_user_to_target_idx_map<R: Rng>(
+    user_to_split_map: &BTreeMap<u32, Vec<Split>>,
+    fold: usize,
+    target_user_list: &[u32],
+    rng: &mut R,
     target_trace_sample_size: usize,
 ) -> HashMap<u32, Vec<usize>> {
-    let user_list: Vec<u32> = user_to_vector_map.keys().cloned().collect();
-    let sampled_clients_list = user_list.iter().choose_multiple(rng, user_sample_size);
     let mut user_to_target_idx_map = HashMap::<u32, Vec<usize>>::new();
-    for client in sampled_clients_list.into_iter() {
-        let traces_list = &user_to_vector_map.get(client).unwrap();
-        let trace_len = traces_list.len();
-        // Split history in 50%/50%
-        let split_idx = trace_len / 2;
-        let indices: Vec<usize> = (split_idx..trace_len).collect();
-        let sampled_target_idx = indices
-            .into_iter()
+    for client in target_user_list.iter() {
+        let split = &user_to_split_map.get(client).unwrap()[fold];
+        let sampled_target_idx = split
+            .target
+            .iter()
+            .cloned()
             .choose_multiple(rng, target_trace_sample_size);
         user_to_target_idx_map.insert(*client, sampled_target_idx);
     }
-    return user_to_target_idx_map;
+    user_to_target_idx_map
 }
 
 /// Samples the observed traces for each user and store sample indices in map.
 ///
-/// The sampled trace indices are stored in a tree map because the tree map stores the keys in a fixed order.
-/// The order is important for consistent sampling and reproducability. The sampled observed traces are found in
-/// the first half of the cronologically ordered history of each user. By setting the trace_sample_size to 0, 50%
-/// of the entire history are sampled.
-pub fn gen_user_to_sample_idx_map<R: Rng, T>(
-    user_to_vector_map: &BTreeMap<u32, Vec<T>>,
+/// The splits are stored in a tree map because the tree map stores the keys in a fixed order.
+/// The order is important for consistent sampling and reproducability. The sampled observed traces are found among
+/// the observed traces of the given fold, by default in the first half of the cronologically ordered history of each
+/// user. Users without observed traces are left out. By setting the trace_sample_size to the maximum, all observed
+/// traces are sampled.
+pub fn gen_user_to_sample_idx_map<R: Rng>(
+    user_to_split_map: &BTreeMap<u32, Vec<Split>>,
+    fold: usize,
     rng: &mut R,
     trace_sample_size: usize,
 ) -> HashMap<u32, Vec<usize>> {
     let mut user_to_sample_idx_map: HashMap<u32, Vec<usize>> = HashMap::new();
-    for (client, traces_list) in user_to_vector_map.into_iter() {
-        let client = client.clone();
-        let trace_len = traces_list.len();
-        let split_idx = trace_len / 2;
-        let indices: Vec<usize> = (0..split_idx).collect();
-        let sampled_idx = indices.into_iter().choose_multiple(rng, trace_sample_size);
-        user_to_sample_idx_map.insert(client, sampled_idx);
+    for (client, split_list) in user_to_split_map.iter() {
+        let split = &split_list[fold];
+        if split.observed.is_empty() {
+            continue;
+        }
+        let sampled_idx = split
+            .observed
+            .iter()
+            .cloned()
+            .choose_multiple(rng, trace_sample_size);
+        user_to_sample_idx_map.insert(*client, sampled_idx);
     }
     user_to_sample_idx_map
 }
@@ -55,15 +160,159 @@ pub fn gen_user_to_sample_idx_map<R: Rng, T>(
 /// Samples a test traces for each user from the observed traces.
 ///
 /// When conducting the linkage attack, the target trace can be compared to all observed traces
-/// or to a randomly selected trace out of the observed traces, called the test trace.
+/// or to a randomly selected trace out of the observed traces, called the test trace. The users
+/// are sorted first, as the order of the hash map differs between runs.
 pub fn gen_user_to_test_idx_map<R: Rng>(
     user_to_sample_idx_map: &HashMap<u32, Vec<usize>>,
     rng: &mut R,
 ) -> HashMap<u32, usize> {
+    let mut sample_idx_lists: Vec<(&u32, &Vec<usize>)> = user_to_sample_idx_map.iter().collect();
+    sample_idx_lists.sort_unstable_by_key(|(client, _)| **client);
     let mut user_to_test_idx_map: HashMap<u32, usize> = HashMap::new();
-    for (client, sample_idx_list) in user_to_sample_idx_map.into_iter() {
+    for (client, sample_idx_list) in sample_idx_lists.into_iter() {
         let test_idx = sample_idx_list.choose(rng).unwrap();
-        user_to_test_idx_map.insert(client.clone(), test_idx.clone());
+        user_to_test_idx_map.insert(*client, *test_idx);
     }
     user_to_test_idx_map
 }
+
+/// Samples the given fraction of the target users, whose observed traces are withheld from the candidates.
+pub fn gen_unseen_users<R: Rng>(
+    target_user_list: &[u32],
+    rng: &mut R,
+    unseen_fraction: f64,
+) -> Vec<u32> {
+    let mut target_user_list: Vec<u32> = target_user_list.to_vec();
+    target_user_list.sort_unstable();
+    let num_unseen = (unseen_fraction * target_user_list.len() as f64).round() as usize;
+    let mut unseen_users = target_user_list.into_iter().choose_multiple(rng, num_unseen);
+    unseen_users.sort_unstable();
+    unseen_users
+}
+
+/// Shuffles the linkage attacks, i.e. the pairs of target user and target trace index, into the order
+/// in which dependent linkage attacks are performed.
+///
+/// The pairs are sorted first, as the order of the hash map differs between runs.
+pub fn gen_attack_order<R: Rng>(
+    user_to_target_idx_map: &HashMap<u32, Vec<usize>>,
+    rng: &mut R,
+) -> Vec<(u32, usize)> {
+    let mut attack_list: Vec<(u32, usize)> = user_to_target_idx_map
+        .iter()
+        .flat_map(|(user, target_idx_list)| target_idx_list.iter().map(move |idx| (*user, *idx)))
+        .collect();
+    attack_list.sort_unstable();
+    attack_list.shuffle(rng);
+    attack_list
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::split::{KFoldSplitter, Splitter};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn gen_idx_maps(user_to_vector_map: &BTreeMap<u32, Vec<f64>>, seed: u64) -> Vec<IdxMaps> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let splitter = KFoldSplitter { num_folds: 3 };
+        let user_to_split_map: BTreeMap<u32, Vec<Split>> = user_to_vector_map
+            .iter()
+            .map(|(user, start_times)| (*user, splitter.split(start_times, &mut rng)))
+            .collect();
+        let target_user_list = gen_target_users(&user_to_split_map, &mut rng, 10);
+        let unseen_users = gen_unseen_users(&target_user_list, &mut rng, 0.2);
+        (0..splitter.num_folds())
+            .map(|fold| {
+                let user_to_target_idx_map =
+                    gen_user_to_target_idx_map(&user_to_split_map, fold, &target_user_list, &mut rng, 2);
+                let mut user_to_sample_idx_map =
+                    gen_user_to_sample_idx_map(&user_to_split_map, fold, &mut rng, 3);
+                for user in unseen_users.iter() {
+                    user_to_sample_idx_map.remove(user);
+                }
+                // Rebuild the map, such that it is iterated in a different order than the sampled one
+                let mut sample_idx_lists: Vec<(u32, Vec<usize>)> = user_to_sample_idx_map.into_iter().collect();
+                sample_idx_lists.reverse();
+                let user_to_sample_idx_map: HashMap<u32, Vec<usize>> = sample_idx_lists.into_iter().collect();
+                let user_to_test_idx_map = gen_user_to_test_idx_map(&user_to_sample_idx_map, &mut rng);
+                IdxMaps {
+                    user_to_target_idx_map,
+                    user_to_sample_idx_map,
+                    user_to_test_idx_map,
+                    unseen_users: unseen_users.clone(),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_yields_same_idx_maps() {
+        let user_to_vector_map: BTreeMap<u32, Vec<f64>> = (0..30)
+            .map(|user| (user, (0..8 + user % 5).map(f64::from).collect()))
+            .collect();
+        let first = gen_idx_maps(&user_to_vector_map, 42);
+        for _ in 0..5 {
+            let second = gen_idx_maps(&user_to_vector_map, 42);
+            for (first, second) in first.iter().zip(second.iter()) {
+                assert_same_idx_maps(first, second);
+            }
+        }
+        for idx_maps in first.iter() {
+            assert!(idx_maps.check(&user_to_vector_map).is_ok());
+        }
+    }
+
+    fn assert_same_idx_maps(first: &IdxMaps, second: &IdxMaps) {
+        assert_eq!(first.user_to_target_idx_map, second.user_to_target_idx_map);
+        assert_eq!(first.user_to_sample_idx_map, second.user_to_sample_idx_map);
+        assert_eq!(first.user_to_test_idx_map, second.user_to_test_idx_map);
+        assert_eq!(first.unseen_users, second.unseen_users);
+    }
+
+    #[test]
+    fn idx_maps_survive_a_round_trip() {
+        let user_to_vector_map: BTreeMap<u32, Vec<f64>> =
+            (0..20).map(|user| (user, (0..9).map(f64::from).collect())).collect();
+        let idx_maps = gen_idx_maps(&user_to_vector_map, 7).remove(0);
+        assert!(!idx_maps.unseen_users.is_empty());
+        let dir = std::env::temp_dir().join(format!("idx_maps_{}", std::process::id()));
+        for file_name in ["map.pkl", "map.json", "map.JSON"] {
+            let path = dir.join(file_name);
+            let path = path.to_str().unwrap();
+            idx_maps.save(path).unwrap();
+            assert_same_idx_maps(&idx_maps, &IdxMaps::load(path).unwrap());
+        }
+        // Maps stored before the open-world evaluation lack the unseen users
+        let path = dir.join("legacy.json");
+        fs::write(
+            &path,
+            r#"{"user_to_target_idx_map":{"1":[2]},"user_to_sample_idx_map":{"1":[0]},"user_to_test_idx_map":{"1":0}}"#,
+        )
+        .unwrap();
+        let legacy = IdxMaps::load(path.to_str().unwrap()).unwrap();
+        assert_eq!(legacy.user_to_target_idx_map, HashMap::from([(1, vec![2])]));
+        assert!(legacy.unseen_users.is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn check_rejects_unknown_users_and_traces() {
+        let user_to_vector_map: BTreeMap<u32, Vec<f64>> = BTreeMap::from([(0, vec![0.0, 1.0]), (1, vec![0.0; 3])]);
+        let idx_maps = |target: (u32, usize), sample: (u32, usize), test: (u32, usize)| IdxMaps {
+            user_to_target_idx_map: HashMap::from([(target.0, vec![target.1])]),
+            user_to_sample_idx_map: HashMap::from([(sample.0, vec![0, sample.1])]),
+            user_to_test_idx_map: HashMap::from([test]),
+            unseen_users: Vec::new(),
+        };
+        assert!(idx_maps((0, 1), (1, 2), (1, 2)).check(&user_to_vector_map).is_ok());
+        // Unknown users
+        assert!(idx_maps((2, 0), (1, 2), (1, 2)).check(&user_to_vector_map).is_err());
+        assert!(idx_maps((0, 1), (2, 0), (1, 2)).check(&user_to_vector_map).is_err());
+        assert!(idx_maps((0, 1), (1, 2), (2, 0)).check(&user_to_vector_map).is_err());
+        // Indices beyond the history of a user
+        assert!(idx_maps((0, 2), (1, 2), (1, 2)).check(&user_to_vector_map).is_err());
+        assert!(idx_maps((0, 1), (1, 3), (1, 2)).check(&user_to_vector_map).is_err());
+        assert!(idx_maps((0, 1), (1, 2), (0, 2)).check(&user_to_vector_map).is_err());
+    }
+}
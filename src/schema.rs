@@ -0,0 +1,143 @@
+use serde::Deserialize;
+use std::{collections::HashMap, error::Error, fmt::Display, fs, str::FromStr};
+
+/// Logical columns of a browsing record that the parsers know about.
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum Column {
+    UserId,
+    Timestamp,
+    Url,
+    Category,
+    ActiveSeconds,
+    Domain,
+    Gender,
+    Age,
+}
+
+impl Column {
+    pub const ALL: [Column; 8] = [
+        Column::UserId,
+        Column::Timestamp,
+        Column::Url,
+        Column::Category,
+        Column::ActiveSeconds,
+        Column::Domain,
+        Column::Gender,
+        Column::Age,
+    ];
+
+    /// Returns the name of the column in the default export format.
+    pub fn default_header(&self) -> &'static str {
+        match self {
+            Column::UserId => "user_id",
+            Column::Timestamp => "timestamp",
+            Column::Url => "url",
+            Column::Category => "category",
+            Column::ActiveSeconds => "active_seconds",
+            Column::Domain => "domain",
+            Column::Gender => "gender",
+            Column::Age => "age",
+        }
+    }
+
+    /// Returns true if every dataset has to provide this column.
+    pub fn is_required(&self) -> bool {
+        matches!(self, Column::UserId | Column::Timestamp | Column::Url)
+    }
+}
+
+impl Display for Column {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.default_header())
+    }
+}
+
+impl FromStr for Column {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Column::ALL
+            .iter()
+            .find(|column| column.default_header() == s.to_lowercase())
+            .copied()
+            .ok_or_else(|| format!("Unknown column supplied: {:?}", s))
+    }
+}
+
+/// Maps the logical columns to the headers found in a specific export.
+#[derive(Debug, Clone)]
+pub struct Schema {
+    headers: HashMap<Column, String>,
+}
+
+#[derive(Deserialize)]
+struct SchemaFile {
+    columns: HashMap<String, String>,
+}
+
+impl Default for Schema {
+    fn default() -> Self {
+        let headers = Column::ALL
+            .iter()
+            .map(|column| (*column, column.default_header().to_string()))
+            .collect();
+        Schema { headers }
+    }
+}
+
+impl Schema {
+    /// Loads the column mapping from a TOML file with a `[columns]` table, e.g. `user_id = "panelist"`.
+    ///
+    /// Columns that are not listed in the file keep their default header.
+    pub fn from_file(path: &str) -> Result<Schema, Box<dyn Error>> {
+        let content = fs::read_to_string(path)?;
+        let file: SchemaFile = toml::from_str(&content)?;
+        let mut schema = Schema::default();
+        for (column, header) in file.columns.iter() {
+            schema.set(Column::from_str(column)?, header);
+        }
+        Ok(schema)
+    }
+
+    /// Overrides the mapping with `column=header` pairs, e.g. as supplied on the command line.
+    pub fn apply_overrides(&mut self, pairs: &[String]) -> Result<(), Box<dyn Error>> {
+        for pair in pairs.iter() {
+            let (column, header) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("Expected column=header, got: {:?}", pair))?;
+            self.set(Column::from_str(column.trim())?, header.trim());
+        }
+        Ok(())
+    }
+
+    pub fn set(&mut self, column: Column, header: &str) {
+        self.headers.insert(column, header.to_string());
+    }
+
+    pub fn header(&self, column: &Column) -> &str {
+        &self.headers[column]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_map_columns_to_headers() {
+        let mut schema = Schema::default();
+        let pairs = vec!["user_id=panelist".to_string(), " Age = birth_year ".to_string()];
+        schema.apply_overrides(&pairs).unwrap();
+        assert_eq!(schema.header(&Column::UserId), "panelist");
+        assert_eq!(schema.header(&Column::Age), "birth_year");
+        // Columns without an override keep their default header
+        assert_eq!(schema.header(&Column::Url), "url");
+    }
+
+    #[test]
+    fn overrides_reject_invalid_pairs() {
+        let mut schema = Schema::default();
+        assert!(schema.apply_overrides(&["user_id".to_string()]).is_err());
+        assert!(schema.apply_overrides(&["browser=agent".to_string()]).is_err());
+        assert_eq!(schema.header(&Column::UserId), "user_id");
+    }
+}
@@ -1,2 +1,3 @@
+pub mod alignment;
 pub mod trace;
 pub mod evaluation;
\ No newline at end of file
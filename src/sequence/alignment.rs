@@ -0,0 +1,481 @@
+use crate::cli::Config;
+use crate::sequence::trace::SeqTrace;
+
+use seal::pair::{AlignmentSet, InMemoryAlignmentMatrix, NeedlemanWunsch, SmithWaterman};
+use std::{fmt::Display, str::FromStr};
+
+/// The share of the difference between the equal and the align score credited to different urls of the same
+/// domain and to different urls or domains of the same category.
+const SAME_DOMAIN_CREDIT: f64 = 0.5;
+const SAME_CATEGORY_CREDIT: f64 = 0.25;
+const HOURS_PER_DAY: u32 = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AlignmentStrategy {
+    #[default]
+    NeedlemanWunsch,
+    SmithWaterman,
+}
+
+impl FromStr for AlignmentStrategy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<AlignmentStrategy, Self::Err> {
+        match s {
+            "nw" => Ok(AlignmentStrategy::NeedlemanWunsch),
+            "sw" => Ok(AlignmentStrategy::SmithWaterman),
+            x => Err(format!("The supplied alignment strategy does not exist: {:?}", x)),
+        }
+    }
+}
+
+impl Display for AlignmentStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            AlignmentStrategy::NeedlemanWunsch => "nw",
+            AlignmentStrategy::SmithWaterman => "sw",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AlignmentScope {
+    #[default]
+    Global,
+    Local,
+}
+
+impl FromStr for AlignmentScope {
+    type Err = String;
+    fn from_str(s: &str) -> Result<AlignmentScope, Self::Err> {
+        match s {
+            "global" => Ok(AlignmentScope::Global),
+            "local" => Ok(AlignmentScope::Local),
+            x => Err(format!("The supplied alignment scope does not exist: {:?}", x)),
+        }
+    }
+}
+
+impl Display for AlignmentScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            AlignmentScope::Global => "global",
+            AlignmentScope::Local => "local",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// How two aligned elements that are not equal are scored.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Substitution {
+    /// Elements are either equal or not.
+    #[default]
+    Exact,
+    /// Different urls of the same domain or category, different domains of the same category and close hours
+    /// earn partial credit.
+    Partial,
+}
+
+impl FromStr for Substitution {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Substitution, Self::Err> {
+        match s {
+            "exact" => Ok(Substitution::Exact),
+            "partial" => Ok(Substitution::Partial),
+            x => Err(format!("The supplied substitution does not exist: {:?}", x)),
+        }
+    }
+}
+
+impl Display for Substitution {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Substitution::Exact => "exact",
+            Substitution::Partial => "partial",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Scores of the alignment operations, in the order expected by the alignment strategies of `seal`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoringMatrix {
+    pub equal: isize,
+    pub align: isize,
+    pub insert: isize,
+    pub delete: isize,
+}
+
+impl Default for ScoringMatrix {
+    fn default() -> Self {
+        ScoringMatrix {
+            equal: 1,
+            align: -1,
+            insert: -1,
+            delete: -1,
+        }
+    }
+}
+
+impl TryFrom<&[isize]> for ScoringMatrix {
+    type Error = String;
+    fn try_from(scores: &[isize]) -> Result<Self, Self::Error> {
+        match scores {
+            [equal, align, insert, delete] => Ok(ScoringMatrix {
+                equal: *equal,
+                align: *align,
+                insert: *insert,
+                delete: *delete,
+            }),
+            _ => Err(format!(
+                "The scoring matrix needs exactly 4 scores (equal, align, insert, delete), got {}",
+                scores.len()
+            )),
+        }
+    }
+}
+
+impl Display for ScoringMatrix {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "[{}, {}, {}, {}]",
+            self.equal, self.align, self.insert, self.delete
+        )
+    }
+}
+
+/// Aligns the target and the reference sequence and returns the score of the alignment.
+pub fn compute_sequence_alignment(
+    strategy: AlignmentStrategy,
+    scope: AlignmentScope,
+    scoring_matrix: &ScoringMatrix,
+    target_trace: &[u32],
+    ref_trace: &[u32],
+) -> f64 {
+    let ScoringMatrix {
+        equal,
+        align,
+        insert,
+        delete,
+    } = *scoring_matrix;
+    let set: AlignmentSet<InMemoryAlignmentMatrix> = match strategy {
+        AlignmentStrategy::NeedlemanWunsch => {
+            let strategy = NeedlemanWunsch::new(equal, align, insert, delete);
+            AlignmentSet::new(target_trace.len(), ref_trace.len(), strategy, |x, y| {
+                target_trace[x] == ref_trace[y]
+            })
+            .unwrap()
+        }
+        AlignmentStrategy::SmithWaterman => {
+            let strategy = SmithWaterman::new(equal, align, insert, delete);
+            AlignmentSet::new(target_trace.len(), ref_trace.len(), strategy, |x, y| {
+                target_trace[x] == ref_trace[y]
+            })
+            .unwrap()
+        }
+    };
+
+    match scope {
+        AlignmentScope::Global => set.global_score() as f64,
+        AlignmentScope::Local => set.local_score() as f64,
+    }
+}
+
+/// The configured alignment of the url, domain, category and hour sequences of two traces.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aligner {
+    pub strategy: AlignmentStrategy,
+    pub scope: AlignmentScope,
+    pub scoring_matrix: ScoringMatrix,
+    /// The additional score of opening a gap, if gaps are scored affinely.
+    pub gap_open: Option<f64>,
+    pub substitution: Substitution,
+}
+
+impl Aligner {
+    pub fn from_config(config: &Config) -> Aligner {
+        Aligner {
+            strategy: config.strategy,
+            scope: config.scope,
+            scoring_matrix: config.scoring_matrix,
+            gap_open: config.gap_open,
+            substitution: config.substitution,
+        }
+    }
+
+    pub fn align_urls(&self, target_trace: &SeqTrace, ref_trace: &SeqTrace) -> f64 {
+        if self.substitution == Substitution::Exact {
+            return self.align_exact(&target_trace.url, &ref_trace.url);
+        }
+        self.align_scored(target_trace.url.len(), ref_trace.url.len(), |x, y| {
+            if target_trace.url[x] == ref_trace.url[y] {
+                self.partial_score(1.0)
+            } else if target_trace.domain[x] == ref_trace.domain[y] {
+                self.partial_score(SAME_DOMAIN_CREDIT)
+            } else if target_trace.category[x] == ref_trace.category[y] {
+                self.partial_score(SAME_CATEGORY_CREDIT)
+            } else {
+                self.partial_score(0.0)
+            }
+        })
+    }
+
+    pub fn align_domains(&self, target_trace: &SeqTrace, ref_trace: &SeqTrace) -> f64 {
+        if self.substitution == Substitution::Exact {
+            return self.align_exact(&target_trace.domain, &ref_trace.domain);
+        }
+        self.align_scored(target_trace.domain.len(), ref_trace.domain.len(), |x, y| {
+            if target_trace.domain[x] == ref_trace.domain[y] {
+                self.partial_score(1.0)
+            } else if target_trace.category[x] == ref_trace.category[y] {
+                self.partial_score(SAME_CATEGORY_CREDIT)
+            } else {
+                self.partial_score(0.0)
+            }
+        })
+    }
+
+    pub fn align_categories(&self, target_trace: &SeqTrace, ref_trace: &SeqTrace) -> f64 {
+        self.align_exact(&target_trace.category, &ref_trace.category)
+    }
+
+    /// Aligns the hour sequences, where different hours earn credit linear in their distance on the clock.
+    pub fn align_hours(&self, target_trace: &SeqTrace, ref_trace: &SeqTrace) -> f64 {
+        if self.substitution == Substitution::Exact {
+            return self.align_exact(&target_trace.hour, &ref_trace.hour);
+        }
+        let half_day = (HOURS_PER_DAY / 2) as f64;
+        self.align_scored(target_trace.hour.len(), ref_trace.hour.len(), |x, y| {
+            let diff = target_trace.hour[x].abs_diff(ref_trace.hour[y]) % HOURS_PER_DAY;
+            let dist = diff.min(HOURS_PER_DAY - diff) as f64;
+            self.partial_score(1.0 - dist / half_day)
+        })
+    }
+
+    /// Divides the score by the self-alignment score of the longer sequence, i.e. the score of aligning each
+    /// of its elements to an equal one, such that identical sequences score one regardless of their length.
+    pub fn normalize(&self, score: f64, target_len: usize, ref_len: usize) -> f64 {
+        let max_score = self.scoring_matrix.equal as f64 * target_len.max(ref_len) as f64;
+        if max_score > 0.0 { score / max_score } else { 0.0 }
+    }
+
+    /// Interpolates between the align score (no credit) and the equal score (full credit).
+    fn partial_score(&self, credit: f64) -> f64 {
+        let ScoringMatrix { equal, align, .. } = self.scoring_matrix;
+        align as f64 + (equal - align) as f64 * credit
+    }
+
+    /// Aligns sequences whose elements are either equal or not.
+    fn align_exact(&self, target_trace: &[u32], ref_trace: &[u32]) -> f64 {
+        if self.gap_open.is_none() {
+            return compute_sequence_alignment(
+                self.strategy,
+                self.scope,
+                &self.scoring_matrix,
+                target_trace,
+                ref_trace,
+            );
+        }
+        self.align_scored(target_trace.len(), ref_trace.len(), |x, y| {
+            if target_trace[x] == ref_trace[y] {
+                self.partial_score(1.0)
+            } else {
+                self.partial_score(0.0)
+            }
+        })
+    }
+
+    /// Aligns two sequences with affine gap scores (Gotoh) and returns the score of the alignment.
+    ///
+    /// A gap of length `k` is scored with `gap_open + k * insert` (or `delete`), aligned elements with the
+    /// given substitution score. Smith-Waterman alignments never fall below zero and may start anywhere. The
+    /// global scope returns the score of the alignment that ends with both sequences, the local scope the
+    /// best score of any alignment.
+    ///
+    /// Like `seal`, skipping reference elements consumes `insert`, skipping target elements `delete` and an
+    /// empty sequence scores zero. For a `gap_open` of zero, the reference elements preceding an alignment of
+    /// the first target element are scored as aligned ones like in `seal`, such that the score equals
+    /// `compute_sequence_alignment` for exact substitutions. Otherwise, they form a gap like any other.
+    fn align_scored<F>(&self, target_len: usize, ref_len: usize, substitution: F) -> f64
+    where
+        F: Fn(usize, usize) -> f64,
+    {
+        if target_len == 0 || ref_len == 0 {
+            return 0.0;
+        }
+        let ScoringMatrix { align, insert, delete, .. } = self.scoring_matrix;
+        let (align, insert, delete) = (align as f64, insert as f64, delete as f64);
+        let gap_open = self.gap_open.unwrap_or(0.0);
+        let floor = match self.strategy {
+            AlignmentStrategy::NeedlemanWunsch => f64::NEG_INFINITY,
+            AlignmentStrategy::SmithWaterman => 0.0,
+        };
+        let boundary = |len: usize, score: f64, open: f64| match self.strategy {
+            AlignmentStrategy::NeedlemanWunsch if len > 0 => open + len as f64 * score,
+            _ => 0.0,
+        };
+
+        // Best scores of the previous and the current row, and of those ending with a deletion per column
+        let mut prev_row: Vec<f64> = (0..=ref_len).map(|y| boundary(y, insert, gap_open)).collect();
+        let leading: Vec<f64> = if gap_open == 0.0 {
+            (0..=ref_len).map(|y| boundary(y, align, 0.0)).collect()
+        } else {
+            prev_row.clone()
+        };
+        let mut row = vec![0.0; ref_len + 1];
+        let mut deletions = vec![f64::NEG_INFINITY; ref_len + 1];
+        let mut best = 0.0;
+
+        for x in 1..=target_len {
+            row[0] = boundary(x, delete, gap_open);
+            let mut insertion = f64::NEG_INFINITY;
+            for y in 1..=ref_len {
+                let diagonal = if x == 1 { leading[y - 1] } else { prev_row[y - 1] };
+                deletions[y] = (deletions[y] + delete).max(prev_row[y] + gap_open + delete);
+                insertion = (insertion + insert).max(row[y - 1] + gap_open + insert);
+                row[y] = (diagonal + substitution(x - 1, y - 1))
+                    .max(deletions[y])
+                    .max(insertion)
+                    .max(floor);
+            }
+            best = row[1..].iter().cloned().fold(best, f64::max);
+            std::mem::swap(&mut prev_row, &mut row);
+        }
+
+        match self.scope {
+            AlignmentScope::Global => prev_row[ref_len],
+            AlignmentScope::Local => best,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    const EPS: f64 = 1e-9;
+
+    fn aligner(
+        strategy: AlignmentStrategy,
+        scope: AlignmentScope,
+        scores: [isize; 4],
+        gap_open: f64,
+        substitution: Substitution,
+    ) -> Aligner {
+        Aligner {
+            strategy,
+            scope,
+            scoring_matrix: ScoringMatrix::try_from(&scores[..]).unwrap(),
+            gap_open: Some(gap_open),
+            substitution,
+        }
+    }
+
+    fn trace(url: &[u32], domain: &[u32], category: &[u32], hour: &[u32]) -> SeqTrace {
+        SeqTrace {
+            url: url.to_vec(),
+            domain: domain.to_vec(),
+            category: category.to_vec(),
+            hour: hour.to_vec(),
+            day: 0,
+            start_time: 0.0,
+            end_time: 0.0,
+            age: String::new(),
+            gender: String::new(),
+        }
+    }
+
+    /// Only without a gap opening score, as `seal` scores leading reference elements as aligned ones.
+    #[test]
+    fn zero_gap_open_matches_seal() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let matrices = [[1, -1, -1, -1], [2, -1, -2, -1], [2, -3, -2, -1], [3, -2, -1, -4]];
+        for scores in matrices.iter() {
+            for strategy in [AlignmentStrategy::NeedlemanWunsch, AlignmentStrategy::SmithWaterman] {
+                for scope in [AlignmentScope::Global, AlignmentScope::Local] {
+                    let aligner = aligner(strategy, scope, *scores, 0.0, Substitution::Exact);
+                    for _ in 0..200 {
+                        let target: Vec<u32> = (0..rng.gen_range(0..8)).map(|_| rng.gen_range(0..3)).collect();
+                        let reference: Vec<u32> = (0..rng.gen_range(0..8)).map(|_| rng.gen_range(0..3)).collect();
+                        let expected =
+                            compute_sequence_alignment(strategy, scope, &aligner.scoring_matrix, &target, &reference);
+                        assert_eq!(
+                            aligner.align_exact(&target, &reference),
+                            expected,
+                            "{} {} {} of {:?} and {:?}",
+                            strategy,
+                            scope,
+                            aligner.scoring_matrix,
+                            target,
+                            reference
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn asymmetric_scores_match_seal() {
+        let aligner = aligner(
+            AlignmentStrategy::NeedlemanWunsch,
+            AlignmentScope::Global,
+            [2, -1, -2, -1],
+            0.0,
+            Substitution::Exact,
+        );
+        assert_eq!(aligner.align_exact(&[2], &[2, 0, 2, 1, 0, 0, 2]), -4.0);
+    }
+
+    #[test]
+    fn affine_gaps_match_known_values() {
+        let nw = aligner(
+            AlignmentStrategy::NeedlemanWunsch,
+            AlignmentScope::Global,
+            [1, -1, -1, -1],
+            -2.0,
+            Substitution::Exact,
+        );
+        // One gap of two deletions (-2 - 2) between two equal elements beats two mismatches
+        assert!((nw.align_exact(&[1, 2, 3, 4], &[1, 4]) - -2.0).abs() < EPS);
+        // A single gap is cheaper than two separate ones
+        assert!((nw.align_exact(&[1, 2, 3, 4, 5], &[1, 4, 5]) - -1.0).abs() < EPS);
+        // A leading gap of unrelated reference elements (-2 - 4) is charged like any other
+        assert!((nw.align_exact(&[1, 2], &[1, 2]) - 2.0).abs() < EPS);
+        assert!((nw.align_exact(&[1, 2], &[5, 6, 7, 8, 1, 2]) - -4.0).abs() < EPS);
+
+        let sw = aligner(
+            AlignmentStrategy::SmithWaterman,
+            AlignmentScope::Local,
+            [2, -1, -1, -1],
+            -2.0,
+            Substitution::Exact,
+        );
+        // The gap (-3) costs more than the preceding match earns, so the best alignment restarts after it
+        assert!((sw.align_exact(&[1, 7, 2, 3], &[1, 2, 3]) - 4.0).abs() < EPS);
+    }
+
+    #[test]
+    fn partial_substitutions_match_known_values() {
+        let aligner = aligner(
+            AlignmentStrategy::NeedlemanWunsch,
+            AlignmentScope::Global,
+            [2, -2, -3, -3],
+            -1.0,
+            Substitution::Partial,
+        );
+        let target = trace(&[1, 2, 3], &[10, 20, 30], &[100, 200, 300], &[3, 12, 0]);
+        let reference = trace(&[1, 4, 5], &[10, 20, 40], &[100, 200, 300], &[23, 18, 12]);
+        // Equal url (2), same domain (0), same category (-1)
+        assert!((aligner.align_urls(&target, &reference) - 1.0).abs() < EPS);
+        // Equal domains (2 + 2), same category (-1)
+        assert!((aligner.align_domains(&target, &reference) - 3.0).abs() < EPS);
+        // Hours apart by 4 (2/3), 6 (0) and 12 (-2) on the clock
+        assert!((aligner.align_hours(&target, &reference) - -4.0 / 3.0).abs() < EPS);
+        // Categories are always compared exactly
+        assert!((aligner.align_categories(&target, &reference) - 6.0).abs() < EPS);
+    }
+}
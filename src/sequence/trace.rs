@@ -1,3 +1,5 @@
+use crate::profile::TypicalTrace;
+use crate::split::TimedTrace;
 use crate::utils;
 
 use serde::{Serialize, Deserialize};
@@ -15,11 +17,27 @@ pub struct SeqTrace {
     pub gender: String,
 }
 
+impl TimedTrace for SeqTrace {
+    fn start_time(&self) -> f64 {
+        self.start_time
+    }
+
+    fn end_time(&self) -> f64 {
+        self.end_time
+    }
+}
+
+impl TypicalTrace for SeqTrace {
+    fn gen_typical(traces: &[&SeqTrace]) -> SeqTrace {
+        gen_typical_trace(traces)
+    }
+}
+
 /// Generates a typical trace from a given list of traces.
 /// 
 /// The length of the typical trace is determined by majority vote, i.e. the length of the majority in the list of traces.
 /// Likewise, the individual values of each data field are specified by majority vote.
-pub fn gen_typical_trace(traces: &Vec<SeqTrace>) -> SeqTrace {
+pub fn gen_typical_trace(traces: &[&SeqTrace]) -> SeqTrace {
     // Get length of typical trace by majority vote
     let lengths: Vec<usize> = traces.iter().map(|cl| cl.url.len()).collect();
     let typical_length = utils::get_most_freq_element(&lengths);
@@ -85,7 +103,7 @@ pub fn gen_typical_trace(traces: &Vec<SeqTrace>) -> SeqTrace {
     }
 
     // Create typical click trace from typical values
-    let typical_trace = SeqTrace {
+    SeqTrace {
         url: typical_urls,
         domain: typical_domains,
         category: typical_categories,
@@ -95,6 +113,5 @@ pub fn gen_typical_trace(traces: &Vec<SeqTrace>) -> SeqTrace {
         end_time: 0.0,
         gender: typical_gender.to_string(),
         age: typical_age.to_string(),
-    };
-    typical_trace
+    }
 }
@@ -0,0 +1,313 @@
+use crate::cli::Config;
+use crate::parse::{self, Record};
+
+use chrono::Datelike;
+use std::{
+    fmt::{Debug, Display},
+    ops::Range,
+    str::FromStr,
+};
+
+/// Decides where the browsing history of a user is split into traces (sessions).
+pub trait Sessionizer: Debug {
+    /// Splits the chronologically ordered records of a single user into sessions.
+    ///
+    /// The sessions are returned as ranges of record indices. Sessions may overlap.
+    fn split(&self, records: &[Record]) -> Vec<Range<usize>>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SessionStrategy {
+    #[default]
+    Inactivity,
+    Day,
+    Week,
+    SlidingWindow,
+    DomainChange,
+}
+
+impl FromStr for SessionStrategy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<SessionStrategy, Self::Err> {
+        match s {
+            "inactivity" => Ok(SessionStrategy::Inactivity),
+            "day" => Ok(SessionStrategy::Day),
+            "week" => Ok(SessionStrategy::Week),
+            "sliding_window" => Ok(SessionStrategy::SlidingWindow),
+            "domain_change" => Ok(SessionStrategy::DomainChange),
+            x => Err(format!("The supplied session strategy does not exist: {:?}", x)),
+        }
+    }
+}
+
+impl Display for SessionStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            SessionStrategy::Inactivity => "inactivity",
+            SessionStrategy::Day => "day",
+            SessionStrategy::Week => "week",
+            SessionStrategy::SlidingWindow => "sliding_window",
+            SessionStrategy::DomainChange => "domain_change",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Creates the sessionizer selected in the configuration.
+pub fn from_config(config: &Config) -> Box<dyn Sessionizer> {
+    match config.session_strategy {
+        SessionStrategy::Inactivity => Box::new(InactivitySessionizer {
+            delay_limit: config.delay_limit,
+        }),
+        SessionStrategy::Day => Box::new(CalendarSessionizer {
+            period: CalendarPeriod::Day,
+        }),
+        SessionStrategy::Week => Box::new(CalendarSessionizer {
+            period: CalendarPeriod::Week,
+        }),
+        SessionStrategy::SlidingWindow => Box::new(SlidingWindowSessionizer {
+            window_size: config.window_size,
+            window_overlap: config.window_overlap,
+        }),
+        SessionStrategy::DomainChange => Box::new(DomainChangeSessionizer {
+            delay_limit: config.delay_limit,
+        }),
+    }
+}
+
+/// Starts a new session whenever the time between two consecutive records reaches the delay limit.
+#[derive(Debug)]
+pub struct InactivitySessionizer {
+    pub delay_limit: f64,
+}
+
+impl Sessionizer for InactivitySessionizer {
+    fn split(&self, records: &[Record]) -> Vec<Range<usize>> {
+        split_where(records, |prev, next| {
+            next.timestamp - prev.timestamp >= self.delay_limit
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum CalendarPeriod {
+    Day,
+    Week,
+}
+
+/// Puts all records of the same calendar day or (ISO) week into one session. Days are in UTC.
+#[derive(Debug)]
+pub struct CalendarSessionizer {
+    pub period: CalendarPeriod,
+}
+
+impl CalendarSessionizer {
+    fn bucket(&self, timestamp: f64) -> (i32, u32) {
+        let datetime = parse::to_datetime(timestamp);
+        match self.period {
+            CalendarPeriod::Day => (datetime.year(), datetime.ordinal()),
+            CalendarPeriod::Week => {
+                let week = datetime.iso_week();
+                (week.year(), week.week())
+            }
+        }
+    }
+}
+
+impl Sessionizer for CalendarSessionizer {
+    fn split(&self, records: &[Record]) -> Vec<Range<usize>> {
+        split_where(records, |prev, next| {
+            self.bucket(prev.timestamp) != self.bucket(next.timestamp)
+        })
+    }
+}
+
+/// Slides a window of a fixed number of records over the history. Consecutive windows share
+/// `window_overlap` records.
+#[derive(Debug)]
+pub struct SlidingWindowSessionizer {
+    pub window_size: usize,
+    pub window_overlap: usize,
+}
+
+impl Sessionizer for SlidingWindowSessionizer {
+    fn split(&self, records: &[Record]) -> Vec<Range<usize>> {
+        let step = self.window_size.saturating_sub(self.window_overlap).max(1);
+        let mut sessions: Vec<Range<usize>> = Vec::new();
+        let mut start: usize = 0;
+        while start + self.window_size <= records.len() {
+            sessions.push(start..start + self.window_size);
+            start += step;
+        }
+        sessions
+    }
+}
+
+/// Starts a new session whenever the domain changes or the delay limit is reached, i.e. each
+/// session is an uninterrupted visit of a single domain.
+#[derive(Debug)]
+pub struct DomainChangeSessionizer {
+    pub delay_limit: f64,
+}
+
+impl Sessionizer for DomainChangeSessionizer {
+    fn split(&self, records: &[Record]) -> Vec<Range<usize>> {
+        split_where(records, |prev, next| {
+            prev.domain != next.domain || next.timestamp - prev.timestamp >= self.delay_limit
+        })
+    }
+}
+
+/// Splits the records into consecutive sessions, starting a new one between every pair of
+/// consecutive records for which the predicate holds.
+fn split_where<F>(records: &[Record], is_boundary: F) -> Vec<Range<usize>>
+where
+    F: Fn(&Record, &Record) -> bool,
+{
+    let mut sessions: Vec<Range<usize>> = Vec::new();
+    if records.is_empty() {
+        return sessions;
+    }
+    let mut start: usize = 0;
+    for (idx, pair) in records.windows(2).enumerate() {
+        if is_boundary(&pair[0], &pair[1]) {
+            sessions.push(start..idx + 1);
+            start = idx + 1;
+        }
+    }
+    sessions.push(start..records.len());
+    sessions
+}
+
+/// Cuts every session that is longer than the maximum length into consecutive pieces.
+pub fn cap_length(sessions: Vec<Range<usize>>, max_len: usize) -> Vec<Range<usize>> {
+    let max_len = max_len.max(1);
+    let mut capped_sessions: Vec<Range<usize>> = Vec::with_capacity(sessions.len());
+    for session in sessions.into_iter() {
+        let mut start = session.start;
+        while session.end - start > max_len {
+            capped_sessions.push(start..start + max_len);
+            start += max_len;
+        }
+        capped_sessions.push(start..session.end);
+    }
+    capped_sessions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{self, ConfigError};
+
+    const DAY: f64 = 86400.0;
+
+    fn record(timestamp: f64, domain: &str) -> Record {
+        Record {
+            user_id: "user".to_string(),
+            timestamp,
+            url: format!("{}/{}", domain, timestamp),
+            category: None,
+            active_seconds: None,
+            domain: Some(domain.to_string()),
+            gender: None,
+            age: None,
+        }
+    }
+
+    fn records(timestamps: &[f64]) -> Vec<Record> {
+        timestamps.iter().map(|timestamp| record(*timestamp, "a.com")).collect()
+    }
+
+    #[test]
+    fn inactivity_splits_at_the_delay_limit() {
+        let sessionizer = InactivitySessionizer { delay_limit: 10.0 };
+        // A gap of exactly the delay limit starts a new session, a slightly shorter one does not
+        let sessions = sessionizer.split(&records(&[0.0, 9.5, 19.5, 29.0, 100.0]));
+        assert_eq!(sessions, vec![0..2, 2..4, 4..5]);
+        assert!(sessionizer.split(&[]).is_empty());
+        assert_eq!(sessionizer.split(&records(&[5.0])), vec![0..1]);
+    }
+
+    #[test]
+    fn calendar_splits_at_midnight_and_monday() {
+        let day = CalendarSessionizer { period: CalendarPeriod::Day };
+        assert_eq!(day.split(&records(&[0.0, DAY - 1.0, DAY, 2.0 * DAY - 0.001])), vec![0..2, 2..4]);
+
+        // 1970-01-01 is a Thursday, so the next ISO week starts four days later
+        let week = CalendarSessionizer { period: CalendarPeriod::Week };
+        assert_eq!(week.split(&records(&[0.0, 4.0 * DAY - 1.0, 4.0 * DAY, 10.0 * DAY])), vec![0..2, 2..4]);
+    }
+
+    #[test]
+    fn calendar_supports_negative_timestamps() {
+        let day = CalendarSessionizer { period: CalendarPeriod::Day };
+        assert_eq!(day.bucket(-1.0), (1969, 365));
+        assert_eq!(day.bucket(-DAY), (1969, 365));
+        assert_eq!(day.bucket(-DAY - 0.5), (1969, 364));
+        assert_eq!(day.split(&records(&[-DAY - 1.0, -1.0, 0.0])), vec![0..1, 1..2, 2..3]);
+    }
+
+    #[test]
+    fn sliding_windows_share_the_overlap() {
+        let records = records(&[0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        let disjoint = SlidingWindowSessionizer {
+            window_size: 3,
+            window_overlap: 0,
+        };
+        // The incomplete last window is dropped
+        assert_eq!(disjoint.split(&records), vec![0..3, 3..6]);
+        let overlapping = SlidingWindowSessionizer {
+            window_size: 3,
+            window_overlap: 2,
+        };
+        assert_eq!(overlapping.split(&records), vec![0..3, 1..4, 2..5, 3..6, 4..7]);
+        let too_large = SlidingWindowSessionizer {
+            window_size: 8,
+            window_overlap: 0,
+        };
+        assert!(too_large.split(&records).is_empty());
+    }
+
+    #[test]
+    fn overlap_of_at_least_the_window_size_is_rejected() {
+        for overlap in ["3", "4"] {
+            let args = [
+                "tracking-by-browsing-behavior",
+                "--session_strategy",
+                "sliding_window",
+                "--window_size",
+                "3",
+                "--window_overlap",
+                overlap,
+            ];
+            match cli::get_config_from(args) {
+                Err(ConfigError::Invalid(problems)) => {
+                    assert!(problems.iter().any(|problem| problem.starts_with("window_overlap")), "{:?}", problems)
+                }
+                result => panic!("Overlap {} is accepted: {:?}", overlap, result.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn domain_change_splits_at_every_new_domain() {
+        let sessionizer = DomainChangeSessionizer { delay_limit: 10.0 };
+        let records = vec![
+            record(0.0, "a.com"),
+            record(1.0, "b.com"),
+            record(2.0, "b.com"),
+            record(12.0, "b.com"),
+            record(13.0, "a.com"),
+        ];
+        // The first record starts a session of its own as the domain changes right after it
+        assert_eq!(sessionizer.split(&records), vec![0..1, 1..3, 3..4, 4..5]);
+    }
+
+    #[test]
+    fn cap_length_cuts_long_sessions() {
+        assert_eq!(cap_length(vec![0..7, 7..9], 3), vec![0..3, 3..6, 6..7, 7..9]);
+        assert_eq!(cap_length(vec![0..6, 6..6], 3), vec![0..3, 3..6, 6..6]);
+        // A maximum length of zero is treated as one
+        assert_eq!(cap_length(vec![0..2, 2..3], 0), vec![0..1, 1..2, 2..3]);
+    }
+}
@@ -0,0 +1,175 @@
+use crate::cli::{Approach, Config};
+use crate::parse::ParsedData;
+use crate::schema::Column;
+
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    error::Error,
+    fs,
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+/// Version of the serialized traces, which has to be increased whenever `FreqTrace`, `SeqTrace` or the
+/// parsing changes, such that snapshots of earlier versions are not loaded even if they still deserialize.
+const FORMAT_VERSION: u32 = 1;
+
+/// Loads the parsed traces from a snapshot if the dataset has been parsed with the same preprocessing
+/// before. Otherwise, the dataset is parsed and the result is stored as a snapshot for subsequent runs.
+///
+/// Snapshots are pickle files named after a hash of the dataset path, its size and modification time
+/// and all configuration values that affect parsing.
+pub fn load_or_parse<T, F>(
+    config: &Config,
+    approach: Approach,
+    parse: F,
+) -> Result<ParsedData<T>, Box<dyn Error>>
+where
+    T: Serialize + DeserializeOwned,
+    F: FnOnce(&Config) -> Result<ParsedData<T>, Box<dyn Error>>,
+{
+    if !config.snapshot {
+        return parse(config);
+    }
+
+    let path = snapshot_path(config, approach)?;
+    if path.exists() {
+        log::info!("Loading parsed traces from snapshot {:?}...", path);
+        let file = fs::File::open(&path)?;
+        match serde_pickle::from_reader(BufReader::new(file), serde_pickle::DeOptions::new()) {
+            Ok(parsed_data) => return Ok(parsed_data),
+            Err(e) => log::warn!("Ignoring unreadable snapshot {:?}: {}", path, e),
+        }
+    }
+
+    let parsed_data = parse(config)?;
+    fs::create_dir_all(&config.snapshot_dir)?;
+    // Write to a temporary file first, such that an interrupted run leaves no corrupt snapshot behind
+    let tmp_path = path.with_extension("pkl.tmp");
+    let mut writer = BufWriter::new(fs::File::create(&tmp_path)?);
+    serde_pickle::to_writer(&mut writer, &parsed_data, serde_pickle::SerOptions::new())?;
+    drop(writer);
+    fs::rename(&tmp_path, &path)?;
+    log::info!("Stored parsed traces in snapshot {:?}", path);
+    Ok(parsed_data)
+}
+
+/// Returns the path of the snapshot for the given configuration and approach.
+fn snapshot_path(config: &Config, approach: Approach) -> Result<PathBuf, Box<dyn Error>> {
+    let key = parse_key(config, approach)?;
+    let file_name = format!("{}-{:016x}.pkl", approach, fnv1a_hash(key.as_bytes()));
+    Ok(Path::new(&config.snapshot_dir).join(file_name))
+}
+
+/// Describes the snapshot format, the dataset and all configuration values that affect parsing. Two configurations with
+/// the same key yield the same parsed traces.
+pub fn parse_key(config: &Config, approach: Approach) -> Result<String, Box<dyn Error>> {
+    let metadata = fs::metadata(&config.path)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or_default();
+    let headers: Vec<&str> = Column::ALL
+        .iter()
+        .map(|column| config.schema.header(column))
+        .collect();
+
+    Ok(format!(
+        "{}|{}|{}|{}|{}|{:?}|{}|{}|{}|{}|{}|{}|{}|{}",
+        FORMAT_VERSION,
+        approach,
+        config.path,
+        metadata.len(),
+        modified,
+        headers,
+        config.session_strategy,
+        config.delay_limit,
+        config.window_size,
+        config.window_overlap,
+        config.max_trace_len,
+        config.min_trace_len,
+        config.max_trace_duration,
+        config.min_num_traces,
+    ))
+}
+
+/// Computes the 64-bit FNV-1a hash, which unlike the hasher of the standard library is stable
+/// across Rust versions and platforms.
+fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes.iter() {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli;
+    use crate::parse::UserIdMap;
+    use std::{cell::Cell, collections::BTreeMap};
+
+    /// Writes a small dataset to a directory in the temporary directory that is unique per test.
+    fn dataset(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("snapshot_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("browsing.csv"), "panelist_id,used_at,url\na,1,x.com\n").unwrap();
+        dir
+    }
+
+    /// Returns a configuration for the dataset in the directory that stores its snapshots next to it.
+    fn config(dir: &Path, args: &[&str]) -> Config {
+        let path = dir.join("browsing.csv").to_str().unwrap().to_string();
+        let snapshot_dir = dir.join("snapshots").to_str().unwrap().to_string();
+        let mut all_args = vec!["tracking-by-browsing-behavior", "--path", &path, "--snapshot_dir", &snapshot_dir];
+        all_args.extend_from_slice(args);
+        cli::get_config_from(all_args).unwrap()
+    }
+
+    #[test]
+    fn snapshots_are_loaded_instead_of_parsing_again() {
+        let dir = dataset("round_trip");
+        let config = config(&dir, &[]);
+        let num_parses = Cell::new(0);
+        let parse = |_: &Config| -> Result<ParsedData<u32>, Box<dyn Error>> {
+            num_parses.set(num_parses.get() + 1);
+            Ok((BTreeMap::from([(0, vec![1, 2]), (1, vec![3])]), UserIdMap::default()))
+        };
+
+        let (parsed, _) = load_or_parse(&config, Approach::Frequency, parse).unwrap();
+        assert_eq!(num_parses.get(), 1);
+        assert!(snapshot_path(&config, Approach::Frequency).unwrap().exists());
+        let (loaded, _) = load_or_parse(&config, Approach::Frequency, parse).unwrap();
+        assert_eq!(num_parses.get(), 1);
+        assert_eq!(loaded, parsed);
+
+        // The other approach has a snapshot of its own
+        load_or_parse(&config, Approach::Sequence, parse).unwrap();
+        assert_eq!(num_parses.get(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn parse_key_changes_with_the_preprocessing() {
+        let dir = dataset("parse_key");
+        let base = config(&dir, &[]);
+        let key = parse_key(&base, Approach::Frequency).unwrap();
+        assert!(key.starts_with(&format!("{}|", FORMAT_VERSION)));
+        assert_eq!(parse_key(&config(&dir, &[]), Approach::Frequency).unwrap(), key);
+        assert_ne!(parse_key(&base, Approach::Sequence).unwrap(), key);
+
+        for args in [
+            &["--delay_limit", "60"][..],
+            &["--session_strategy", "day"],
+            &["--columns", "user_id=user"],
+        ] {
+            let changed = config(&dir, args);
+            assert_ne!(parse_key(&changed, Approach::Frequency).unwrap(), key, "{:?}", args);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
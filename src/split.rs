@@ -0,0 +1,318 @@
+use crate::cli::Config;
+use crate::utils;
+
+use rand::{prelude::SliceRandom, RngCore};
+use std::{
+    fmt::{Debug, Display},
+    str::FromStr,
+};
+
+/// Traces that carry the time at which they start and end, such that histories can be split in time.
+pub trait TimedTrace {
+    fn start_time(&self) -> f64;
+    fn end_time(&self) -> f64;
+}
+
+/// The trace indices of a single user from which the observed and the target traces of a fold are sampled.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Split {
+    pub observed: Vec<usize>,
+    pub target: Vec<usize>,
+}
+
+/// Decides which traces of a user are observed by the attacker and which are the targets of linkage attacks.
+pub trait Splitter: Debug {
+    /// Splits the chronologically ordered traces of a single user, given by their start times, into one
+    /// split per fold.
+    fn split(&self, start_times: &[f64], rng: &mut dyn RngCore) -> Vec<Split>;
+
+    /// Returns the number of folds, i.e. the number of evaluations whose results are aggregated.
+    fn num_folds(&self) -> usize {
+        1
+    }
+
+    /// Returns the number of target traces every fold is guaranteed to provide for a user with the
+    /// given number of traces, None if it depends on the traces themselves.
+    fn min_num_targets(&self, num_traces: usize) -> Option<usize>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SplitStrategy {
+    #[default]
+    Chronological,
+    Cutoff,
+    Random,
+    LeaveOneOut,
+    KFold,
+}
+
+impl FromStr for SplitStrategy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<SplitStrategy, Self::Err> {
+        match s {
+            "chronological" => Ok(SplitStrategy::Chronological),
+            "cutoff" => Ok(SplitStrategy::Cutoff),
+            "random" => Ok(SplitStrategy::Random),
+            "leave_one_out" => Ok(SplitStrategy::LeaveOneOut),
+            "k_fold" => Ok(SplitStrategy::KFold),
+            x => Err(format!("The supplied split strategy does not exist: {:?}", x)),
+        }
+    }
+}
+
+impl Display for SplitStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            SplitStrategy::Chronological => "chronological",
+            SplitStrategy::Cutoff => "cutoff",
+            SplitStrategy::Random => "random",
+            SplitStrategy::LeaveOneOut => "leave_one_out",
+            SplitStrategy::KFold => "k_fold",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Creates the splitter selected in the configuration.
+pub fn from_config(config: &Config) -> Box<dyn Splitter> {
+    match config.split_strategy {
+        SplitStrategy::Chronological => Box::new(ChronologicalSplitter {
+            ratio: config.split_ratio,
+        }),
+        SplitStrategy::Cutoff => Box::new(CutoffSplitter {
+            cutoff: config.split_cutoff.unwrap(),
+        }),
+        SplitStrategy::Random => Box::new(RandomSplitter {
+            ratio: config.split_ratio,
+        }),
+        SplitStrategy::LeaveOneOut => Box::new(LeaveOneOutSplitter),
+        SplitStrategy::KFold => Box::new(KFoldSplitter {
+            num_folds: config.num_folds,
+        }),
+    }
+}
+
+/// Returns the path of the sampled maps of the given fold, e.g. `map.fold2.pkl` for the second fold.
+/// Without folds, the path is returned as is.
+pub fn fold_path(path: &str, fold: usize, num_folds: usize) -> String {
+    if num_folds <= 1 {
+        return path.to_string();
+    }
+    utils::insert_suffix(path, &format!("fold{}", fold + 1))
+}
+
+/// Returns the number of observed traces for the given ratio, such that there is at least one observed
+/// and one target trace.
+fn num_observed(num_traces: usize, ratio: f64) -> usize {
+    ((num_traces as f64 * ratio) as usize).clamp(1, num_traces.saturating_sub(1).max(1))
+}
+
+/// Observes the given share of the earliest traces and targets the remaining ones.
+#[derive(Debug)]
+pub struct ChronologicalSplitter {
+    pub ratio: f64,
+}
+
+impl Splitter for ChronologicalSplitter {
+    fn split(&self, start_times: &[f64], _rng: &mut dyn RngCore) -> Vec<Split> {
+        let split_idx = num_observed(start_times.len(), self.ratio);
+        vec![Split {
+            observed: (0..split_idx).collect(),
+            target: (split_idx..start_times.len()).collect(),
+        }]
+    }
+
+    fn min_num_targets(&self, num_traces: usize) -> Option<usize> {
+        Some(num_traces - num_observed(num_traces, self.ratio))
+    }
+}
+
+/// Observes the traces that start before the cutoff timestamp and targets the ones that start later.
+/// Users without traces before the cutoff are not among the candidates, users without traces after
+/// the cutoff are no targets.
+#[derive(Debug)]
+pub struct CutoffSplitter {
+    pub cutoff: f64,
+}
+
+impl Splitter for CutoffSplitter {
+    fn split(&self, start_times: &[f64], _rng: &mut dyn RngCore) -> Vec<Split> {
+        let split_idx = start_times.partition_point(|start_time| *start_time < self.cutoff);
+        vec![Split {
+            observed: (0..split_idx).collect(),
+            target: (split_idx..start_times.len()).collect(),
+        }]
+    }
+
+    fn min_num_targets(&self, _num_traces: usize) -> Option<usize> {
+        None
+    }
+}
+
+/// Observes the given share of randomly selected traces and targets the remaining ones.
+#[derive(Debug)]
+pub struct RandomSplitter {
+    pub ratio: f64,
+}
+
+impl Splitter for RandomSplitter {
+    fn split(&self, start_times: &[f64], rng: &mut dyn RngCore) -> Vec<Split> {
+        let mut indices: Vec<usize> = (0..start_times.len()).collect();
+        indices.shuffle(rng);
+        let (observed, target) = indices.split_at(num_observed(start_times.len(), self.ratio));
+        let mut split = Split {
+            observed: observed.to_vec(),
+            target: target.to_vec(),
+        };
+        split.observed.sort_unstable();
+        split.target.sort_unstable();
+        vec![split]
+    }
+
+    fn min_num_targets(&self, num_traces: usize) -> Option<usize> {
+        Some(num_traces - num_observed(num_traces, self.ratio))
+    }
+}
+
+/// Targets the most recent trace and observes all earlier ones.
+#[derive(Debug)]
+pub struct LeaveOneOutSplitter;
+
+impl Splitter for LeaveOneOutSplitter {
+    fn split(&self, start_times: &[f64], _rng: &mut dyn RngCore) -> Vec<Split> {
+        let split_idx = start_times.len().saturating_sub(1);
+        vec![Split {
+            observed: (0..split_idx).collect(),
+            target: (split_idx..start_times.len()).collect(),
+        }]
+    }
+
+    fn min_num_targets(&self, num_traces: usize) -> Option<usize> {
+        Some(num_traces.min(1))
+    }
+}
+
+/// Distributes the traces randomly over the folds. Each fold targets its own traces and observes the
+/// traces of all other folds, such that every trace is targeted once over all folds.
+#[derive(Debug)]
+pub struct KFoldSplitter {
+    pub num_folds: usize,
+}
+
+impl Splitter for KFoldSplitter {
+    fn split(&self, start_times: &[f64], rng: &mut dyn RngCore) -> Vec<Split> {
+        let mut indices: Vec<usize> = (0..start_times.len()).collect();
+        indices.shuffle(rng);
+        let mut fold_list: Vec<Vec<usize>> = vec![Vec::new(); self.num_folds];
+        for (position, idx) in indices.into_iter().enumerate() {
+            fold_list[position % self.num_folds].push(idx);
+        }
+        (0..self.num_folds)
+            .map(|fold| {
+                let mut split = Split::default();
+                for (other_fold, idx_list) in fold_list.iter().enumerate() {
+                    if other_fold == fold {
+                        split.target.extend(idx_list);
+                    } else {
+                        split.observed.extend(idx_list);
+                    }
+                }
+                split.observed.sort_unstable();
+                split.target.sort_unstable();
+                split
+            })
+            .collect()
+    }
+
+    fn num_folds(&self) -> usize {
+        self.num_folds
+    }
+
+    fn min_num_targets(&self, num_traces: usize) -> Option<usize> {
+        Some(num_traces / self.num_folds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn start_times(num_traces: usize) -> Vec<f64> {
+        (0..num_traces).map(|idx| idx as f64).collect()
+    }
+
+    #[test]
+    fn k_fold_targets_every_trace_once() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for num_folds in 2..=4 {
+            let splitter = KFoldSplitter { num_folds };
+            for num_traces in num_folds..=13 {
+                let split_list = splitter.split(&start_times(num_traces), &mut rng);
+                assert_eq!(split_list.len(), num_folds);
+
+                let mut targeted: Vec<usize> = split_list.iter().flat_map(|split| split.target.clone()).collect();
+                targeted.sort_unstable();
+                assert_eq!(targeted, (0..num_traces).collect::<Vec<usize>>());
+
+                for split in split_list.iter() {
+                    let mut all: Vec<usize> = split.observed.iter().chain(split.target.iter()).copied().collect();
+                    all.sort_unstable();
+                    assert_eq!(all, (0..num_traces).collect::<Vec<usize>>(), "observed and target overlap");
+                    assert!(split.target.len() >= splitter.min_num_targets(num_traces).unwrap());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn num_observed_leaves_observed_and_target_traces() {
+        assert_eq!(num_observed(10, 0.5), 5);
+        assert_eq!(num_observed(10, 0.0), 1);
+        assert_eq!(num_observed(10, 0.95), 9);
+        assert_eq!(num_observed(10, 1.0), 9);
+        assert_eq!(num_observed(2, 0.0), 1);
+        assert_eq!(num_observed(2, 1.0), 1);
+        // A single trace cannot be split, it is observed
+        assert_eq!(num_observed(1, 0.5), 1);
+    }
+
+    #[test]
+    fn chronological_observes_the_earliest_traces() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let split_list = ChronologicalSplitter { ratio: 0.5 }.split(&start_times(5), &mut rng);
+        assert_eq!(split_list, vec![Split { observed: vec![0, 1], target: vec![2, 3, 4] }]);
+
+        let splitter = ChronologicalSplitter { ratio: 1.0 };
+        let split_list = splitter.split(&start_times(3), &mut rng);
+        assert_eq!(split_list, vec![Split { observed: vec![0, 1], target: vec![2] }]);
+        assert_eq!(splitter.min_num_targets(3), Some(1));
+
+        let splitter = ChronologicalSplitter { ratio: 0.0 };
+        let split_list = splitter.split(&start_times(3), &mut rng);
+        assert_eq!(split_list, vec![Split { observed: vec![0], target: vec![1, 2] }]);
+        assert_eq!(splitter.min_num_targets(3), Some(2));
+    }
+
+    #[test]
+    fn cutoff_splits_at_the_timestamp() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let times = [1.0, 2.0, 3.0, 4.0];
+        let split = |cutoff: f64| CutoffSplitter { cutoff }.split(&times, &mut StdRng::seed_from_u64(0));
+        assert_eq!(split(2.5), vec![Split { observed: vec![0, 1], target: vec![2, 3] }]);
+        // Traces starting at the cutoff are targets
+        assert_eq!(split(2.0), vec![Split { observed: vec![0], target: vec![1, 2, 3] }]);
+        assert_eq!(split(0.0), vec![Split { observed: vec![], target: vec![0, 1, 2, 3] }]);
+        assert_eq!(split(5.0), vec![Split { observed: vec![0, 1, 2, 3], target: vec![] }]);
+        assert_eq!(CutoffSplitter { cutoff: 0.0 }.split(&[], &mut rng), vec![Split::default()]);
+        assert_eq!(CutoffSplitter { cutoff: 0.0 }.min_num_targets(4), None);
+    }
+
+    #[test]
+    fn fold_path_inserts_the_fold() {
+        assert_eq!(fold_path("tmp/map.pkl", 1, 3), "tmp/map.fold2.pkl");
+        assert_eq!(fold_path("tmp/map.json", 0, 3), "tmp/map.fold1.json");
+        assert_eq!(fold_path("tmp/map", 2, 3), "tmp/map.fold3");
+        assert_eq!(fold_path("tmp/map.pkl", 0, 1), "tmp/map.pkl");
+    }
+}
@@ -0,0 +1,198 @@
+use rand::Rng;
+
+/// Computes the percentile bootstrap confidence interval of the mean of the given values.
+pub fn bootstrap_ci<R: Rng>(
+    data: &[f64],
+    rng: &mut R,
+    num_samples: usize,
+    confidence_level: f64,
+) -> (f64, f64) {
+    if data.is_empty() || num_samples == 0 {
+        return (f64::NAN, f64::NAN);
+    }
+    let mut means: Vec<f64> = (0..num_samples)
+        .map(|_| {
+            let sum: f64 = (0..data.len()).map(|_| data[rng.gen_range(0..data.len())]).sum();
+            sum / data.len() as f64
+        })
+        .collect();
+    means.sort_unstable_by(|a, b| a.total_cmp(b));
+    percentile_interval(&means, confidence_level)
+}
+
+/// Result of comparing two paired lists of successes (1.0) and failures (0.0).
+#[derive(Debug)]
+pub struct PairedComparison {
+    /// Number of pairs in which only the first run succeeded.
+    pub only_first: usize,
+    /// Number of pairs in which only the second run succeeded.
+    pub only_second: usize,
+    /// Bootstrap confidence interval of the difference in accuracy (second minus first).
+    pub diff_ci: (f64, f64),
+    /// Two-sided p-value of the exact McNemar test.
+    pub mcnemar_p: f64,
+    /// Two-sided p-value of the paired bootstrap test.
+    pub bootstrap_p: f64,
+}
+
+/// Compares the accuracy of two runs on the same linkage attacks with McNemar's test and a paired bootstrap.
+///
+/// Fails if the runs do not share any linkage attack, as neither test is defined without pairs.
+pub fn compare_paired<R: Rng>(
+    first: &[f64],
+    second: &[f64],
+    rng: &mut R,
+    num_samples: usize,
+    confidence_level: f64,
+) -> Result<PairedComparison, String> {
+    if first.len() != second.len() {
+        return Err(format!(
+            "The runs have a different number of linkage attacks: {} and {}",
+            first.len(),
+            second.len()
+        ));
+    }
+    if first.is_empty() {
+        return Err("There are no paired linkage attacks to compare".to_string());
+    }
+    let diff_list: Vec<f64> = first.iter().zip(second.iter()).map(|(a, b)| b - a).collect();
+    let only_first = diff_list.iter().filter(|diff| **diff < 0.0).count();
+    let only_second = diff_list.iter().filter(|diff| **diff > 0.0).count();
+
+    let mut diff_means: Vec<f64> = (0..num_samples)
+        .map(|_| {
+            let sum: f64 = (0..diff_list.len())
+                .map(|_| diff_list[rng.gen_range(0..diff_list.len())])
+                .sum();
+            sum / diff_list.len() as f64
+        })
+        .collect();
+    diff_means.sort_unstable_by(|a, b| a.total_cmp(b));
+
+    // The share of resampled differences on either side of zero estimates how likely the sign of the difference flips
+    let bootstrap_p = if diff_means.is_empty() {
+        f64::NAN
+    } else {
+        let num_non_positive = diff_means.iter().filter(|diff| **diff <= 0.0).count();
+        let num_non_negative = diff_means.iter().filter(|diff| **diff >= 0.0).count();
+        (2.0 * num_non_positive.min(num_non_negative) as f64 / diff_means.len() as f64).min(1.0)
+    };
+
+    Ok(PairedComparison {
+        only_first,
+        only_second,
+        diff_ci: if diff_means.is_empty() {
+            (f64::NAN, f64::NAN)
+        } else {
+            percentile_interval(&diff_means, confidence_level)
+        },
+        mcnemar_p: mcnemar_exact(only_first, only_second),
+        bootstrap_p,
+    })
+}
+
+/// Computes the two-sided p-value of the exact McNemar test, i.e. a binomial test of the discordant
+/// pairs with success probability 0.5.
+pub fn mcnemar_exact(only_first: usize, only_second: usize) -> f64 {
+    let n = only_first + only_second;
+    if n == 0 {
+        return 1.0;
+    }
+    let k = only_first.min(only_second);
+    // Sum the binomial probabilities in log space to avoid overflow for many discordant pairs
+    let ln_half_pow_n = n as f64 * 0.5_f64.ln();
+    let mut ln_binom = 0.0;
+    let mut tail = 0.0;
+    for i in 0..=k {
+        if i > 0 {
+            ln_binom += ((n - i + 1) as f64).ln() - (i as f64).ln();
+        }
+        tail += (ln_binom + ln_half_pow_n).exp();
+    }
+    (2.0 * tail).min(1.0)
+}
+
+/// Returns the interval between the lower and upper percentile of the sorted values for the given confidence level.
+fn percentile_interval(sorted: &[f64], confidence_level: f64) -> (f64, f64) {
+    let alpha = 1.0 - confidence_level;
+    let percentile = |p: f64| {
+        let idx = (p * (sorted.len() - 1) as f64).round() as usize;
+        sorted[idx.min(sorted.len() - 1)]
+    };
+    (percentile(alpha / 2.0), percentile(1.0 - alpha / 2.0))
+}
+
+/// Converts a list of hits to a list of successes (1.0) and failures (0.0), e.g. to compute accuracies.
+pub fn to_share_list<I: IntoIterator<Item = bool>>(hits: I) -> Vec<f64> {
+    hits.into_iter().map(|hit| if hit { 1.0 } else { 0.0 }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    const EPS: f64 = 1e-12;
+
+    #[test]
+    fn mcnemar_matches_known_values() {
+        // 2 * (C(10, 0) + C(10, 1)) / 2^10
+        assert!((mcnemar_exact(1, 9) - 22.0 / 1024.0).abs() < EPS);
+        assert!((mcnemar_exact(9, 1) - 22.0 / 1024.0).abs() < EPS);
+        assert!((mcnemar_exact(0, 5) - 2.0 / 32.0).abs() < EPS);
+        assert_eq!(mcnemar_exact(0, 0), 1.0);
+        assert_eq!(mcnemar_exact(5, 5), 1.0);
+        // Many discordant pairs neither overflow nor exceed one
+        assert_eq!(mcnemar_exact(1000, 1000), 1.0);
+        assert!(mcnemar_exact(400, 600) < 1e-9);
+    }
+
+    #[test]
+    fn percentile_interval_picks_the_bounds() {
+        let sorted: Vec<f64> = (0..=100).map(f64::from).collect();
+        assert_eq!(percentile_interval(&sorted, 0.9), (5.0, 95.0));
+        assert_eq!(percentile_interval(&sorted, 0.5), (25.0, 75.0));
+    }
+
+    #[test]
+    fn bootstrap_ci_covers_the_mean() {
+        let data: Vec<f64> = (0..100).map(|i| f64::from(i % 2)).collect();
+        let ci = bootstrap_ci(&data, &mut StdRng::seed_from_u64(0), 1000, 0.95);
+        assert_eq!(ci, bootstrap_ci(&data, &mut StdRng::seed_from_u64(0), 1000, 0.95));
+        // The normal approximation is 0.5 +- 1.96 * 0.05
+        assert!(ci.0 > 0.38 && ci.0 < 0.42, "{:?}", ci);
+        assert!(ci.1 > 0.58 && ci.1 < 0.62, "{:?}", ci);
+
+        let constant = vec![0.25; 10];
+        assert_eq!(bootstrap_ci(&constant, &mut StdRng::seed_from_u64(0), 100, 0.95), (0.25, 0.25));
+        let empty = bootstrap_ci(&[], &mut StdRng::seed_from_u64(0), 100, 0.95);
+        assert!(empty.0.is_nan() && empty.1.is_nan());
+    }
+
+    #[test]
+    fn compare_paired_counts_discordant_pairs() {
+        let first = [1.0, 1.0, 0.0, 0.0, 1.0];
+        let second = [1.0, 0.0, 1.0, 1.0, 1.0];
+        let comparison = compare_paired(&first, &second, &mut StdRng::seed_from_u64(0), 1000, 0.95).unwrap();
+        assert_eq!(comparison.only_first, 1);
+        assert_eq!(comparison.only_second, 2);
+        assert!((comparison.mcnemar_p - 1.0).abs() < EPS);
+        assert!(comparison.diff_ci.0 <= 0.2 && comparison.diff_ci.1 >= 0.2);
+
+        let identical = compare_paired(&first, &first, &mut StdRng::seed_from_u64(0), 1000, 0.95).unwrap();
+        assert_eq!(identical.diff_ci, (0.0, 0.0));
+        assert_eq!(identical.mcnemar_p, 1.0);
+        assert_eq!(identical.bootstrap_p, 1.0);
+
+        let better = compare_paired(&[0.0; 20], &[1.0; 20], &mut StdRng::seed_from_u64(0), 1000, 0.95).unwrap();
+        assert_eq!(better.diff_ci, (1.0, 1.0));
+        assert_eq!(better.bootstrap_p, 0.0);
+        assert!((better.mcnemar_p - 2.0 / 2f64.powi(20)).abs() < EPS);
+    }
+
+    #[test]
+    fn compare_paired_requires_paired_attacks() {
+        assert!(compare_paired(&[], &[], &mut StdRng::seed_from_u64(0), 1000, 0.95).is_err());
+        assert!(compare_paired(&[1.0], &[1.0, 0.0], &mut StdRng::seed_from_u64(0), 1000, 0.95).is_err());
+    }
+}
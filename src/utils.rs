@@ -1,72 +1,139 @@
 use crate::cli::Config;
+use crate::decay;
+use crate::field_weights::FieldWeights;
+use crate::open_world::{self, OpenWorldPoint};
+use crate::parse::UserIdMap;
+use crate::split;
+use crate::stats;
 
 use csv::WriterBuilder;
-use indexmap::set::IndexSet;
 use ordered_float::OrderedFloat;
-use serde::Serialize;
-use std::{collections::HashMap, error::Error};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt::Display,
+    fs,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
 
 const EVAL_PATH: &str = "tmp/evaluation";
+pub const USER_EVAL_PATH: &str = "tmp/user_evaluation";
+pub const USER_ID_MAP_PATH: &str = "tmp/user_id_map";
 
-/// Normalizes the values of a given vector.
-pub fn normalize_vector(vector: &mut [f64]) {
-    let norm = vector.iter().map(|x| *x * *x).sum::<f64>().sqrt();
-    if norm > 0. {
-        for i in vector.iter_mut() {
-            *i = *i / norm;
-        }
-    }
+/// Outcome of a single linkage attack: the rank of the target user among all candidate users.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttackResult {
+    /// Index of the target trace, None if all target traces of the user are combined in one attack.
+    pub target_idx: Option<usize>,
+    pub rank: usize,
+    pub num_candidates: usize,
+    /// Whether the target user is among the candidates, which is not the case for unseen users in the open world.
+    pub seen: bool,
+    /// Score (or distance) of the best matching candidate, None if there are no candidates.
+    pub best_score: Option<f64>,
+    /// Best matching candidate users with their best score, only kept if the attacks are logged.
+    pub candidates: Vec<(u32, f64)>,
+    /// Whether the acceptance policy of the dependent linkage attacks accepted the best match.
+    pub accepted: Option<bool>,
+    /// User to which the target trace is linked by a dependent linkage attack, which is not
+    /// necessarily the target user.
+    pub linked_user: Option<u32>,
+    /// Days between the latest observed trace of the target user and the target trace, if known.
+    pub gap_days: Option<f64>,
 }
 
-/// Generates a vector of fixed size from a given frequency map. The size depends on the size set of unique values.
-pub fn gen_vector_from_freq_map(
-    type_to_freq_map: &HashMap<String, u32>,
-    set: &IndexSet<String>,
-) -> Vec<u32> {
-    let mut vector: Vec<u32> = vec![0; set.len()];
-    for (key, value) in type_to_freq_map.into_iter() {
-        vector[set.get_full(key).unwrap().0] = value.clone();
+impl AttackResult {
+    /// Determines the rank of the target user from the candidate tuples, sorted from the best to the worst match.
+    ///
+    /// Users that occur in several tuples (one per compared trace) are ranked by their best match. Target
+    /// users that are not among the candidates are ranked last. The given number of best matching
+    /// candidates is kept for the attack log.
+    pub fn from_sorted_tuples(
+        user_target: &u32,
+        target_idx: Option<usize>,
+        tuples: &[(u32, OrderedFloat<f64>)],
+        num_logged_candidates: usize,
+    ) -> AttackResult {
+        let mut ranked_users: HashSet<u32> = HashSet::with_capacity(tuples.len());
+        let mut rank: Option<usize> = None;
+        let mut candidates: Vec<(u32, f64)> = Vec::with_capacity(num_logged_candidates);
+        for (user, score) in tuples.iter() {
+            if ranked_users.insert(*user) {
+                if user == user_target {
+                    rank = Some(ranked_users.len());
+                }
+                if candidates.len() < num_logged_candidates {
+                    candidates.push((*user, score.into_inner()));
+                }
+            }
+        }
+        let num_candidates = ranked_users.len();
+        AttackResult {
+            target_idx,
+            rank: rank.unwrap_or(num_candidates + 1),
+            num_candidates,
+            seen: rank.is_some(),
+            best_score: tuples.first().map(|(_, score)| score.into_inner()),
+            candidates,
+            accepted: None,
+            linked_user: None,
+            gap_days: None,
+        }
     }
-    vector
-}
 
-/// Flattens a nested vector.
-pub fn flatten<T>(nested: Vec<Vec<T>>) -> Vec<T> {
-    nested.into_iter().flatten().collect()
-}
+    pub fn is_in_top_k(&self, k: usize) -> bool {
+        self.rank <= k
+    }
 
-/// Generates a binary vector from a given string value and a set of all string values.
-pub fn gen_vector_from_str(s: &str, set: &IndexSet<String>) -> Vec<u32> {
-    let mut vector: Vec<u32> = vec![0; set.len()];
-    vector[set.get_full(s).unwrap().0] = 1;
-    vector
+    pub fn is_in_top_10_percent(&self) -> bool {
+        self.rank <= (0.1 * self.num_candidates as f64) as usize
+    }
 }
 
-/// Returns true if the target value can be found in the top k of values.
-pub fn is_target_in_top_k(user_target: &u32, tuples: &[(u32, OrderedFloat<f64>)]) -> bool {
-    tuples.iter().any(|(a, _)| a == user_target)
-}
+/// Outcomes of all linkage attacks against a single target user.
+pub type UserResult = (u32, Vec<AttackResult>);
 
 /// Returns the most frequent element in a given vector of values. The values can be of arbitrary type.
+///
+/// Ties are broken by the smallest value rather than by the iteration order of the hash map, which differs
+/// between processes, such that typical traces are reproducible.
 pub fn get_most_freq_element<T>(vector: &[T]) -> T
 where
-    T: std::cmp::Eq + std::hash::Hash + Copy,
+    T: std::cmp::Ord + std::hash::Hash + Copy,
 {
     let mut map = HashMap::new();
-    for e in vector.into_iter() {
+    for e in vector.iter() {
         *map.entry(e).or_insert(0) += 1;
     }
-    let option = map.into_iter().max_by_key(|(_, v)| *v).map(|(k, _)| k);
-    let most_frequent_ele = *option.unwrap();
-    most_frequent_ele
+    let option = map
+        .into_iter()
+        .max_by_key(|(k, v)| (*v, std::cmp::Reverse(*k)))
+        .map(|(k, _)| k);
+    *option.unwrap()
 }
 
 /// Calculates the mean for a vector of values.
 pub fn mean(data: &[f64]) -> f64 {
     let sum = data.iter().sum::<f64>();
     let count = data.len();
-    let mean = sum / count as f64;
-    mean
+    sum / count as f64
+}
+
+/// Calculates the median for a vector of values.
+pub fn median(data: &[f64]) -> f64 {
+    let mut sorted = data.to_vec();
+    sorted.sort_unstable_by(|a, b| a.total_cmp(b));
+    let mid = sorted.len() / 2;
+    if sorted.is_empty() {
+        f64::NAN
+    } else if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    }
 }
 
 /// Calculates the standard deviation for a vector of values.
@@ -76,7 +143,7 @@ pub fn std_deviation(data: &[f64]) -> f64 {
     let variance = data
         .iter()
         .map(|value| {
-            let diff = data_mean - (*value as f64);
+            let diff = data_mean - *value;
             diff * diff
         })
         .sum::<f64>()
@@ -84,30 +151,15 @@ pub fn std_deviation(data: &[f64]) -> f64 {
     variance.sqrt()
 }
 
-/// Determines the success of the linkage attack based on simple heuristic.
-///
-/// Checks whether the difference from the 1st to the 2nd is significant greater
-/// than the difference from the 2nd to the 3rd in order to decide
-/// the success of the linkage attack. The threshold is set to 0.75.
-pub fn is_significant(tuples: &[(u32, OrderedFloat<f64>)]) -> bool {
-    let mut significant = false;
-    let diff_1st_to_2nd = tuples.get(0).unwrap().1 - tuples.get(1).unwrap().1;
-    let diff_2nd_to_3rd = tuples.get(1).unwrap().1 - tuples.get(2).unwrap().1;
-    let siginificance_level: OrderedFloat<f64> =
-        OrderedFloat(1.0) - (diff_2nd_to_3rd / diff_1st_to_2nd);
-    if siginificance_level >= OrderedFloat(0.75) {
-        significant = true;
-    }
-    significant
-}
-
 #[derive(Serialize)]
 struct Row {
+    session_strategy: String,
     delay_limit: f64,
+    window_size: usize,
+    window_overlap: usize,
     max_trace_len: usize,
     min_trace_len: usize,
     max_trace_duration: f64,
-    max_rate: f64,
     min_num_traces: usize,
     path: String,
     seed: u64,
@@ -129,20 +181,247 @@ struct Row {
     top_10_std: f64,
     top_10_percent: f64,
     top_10_percent_std: f64,
+    mrr: f64,
+    median_rank: f64,
+    top_k: String,
+    cmc: String,
+    top_1_ci_low: f64,
+    top_1_ci_high: f64,
+    top_10_ci_low: f64,
+    top_10_ci_high: f64,
+    top_10_percent_ci_low: f64,
+    top_10_percent_ci_high: f64,
+    mrr_ci_low: f64,
+    mrr_ci_high: f64,
+    unseen_fraction: f64,
+    match_threshold: Option<f64>,
+    precision: f64,
+    recall: Option<f64>,
+    false_match_rate: Option<f64>,
+    acceptance: String,
+    acceptance_threshold: Option<f64>,
+    num_linked: usize,
+    num_wrongly_linked: usize,
+    split_strategy: String,
+    split_ratio: f64,
+    split_cutoff: Option<f64>,
+    num_folds: usize,
+    weighting: String,
+    field_weights: String,
+    validation_top_1: Option<f64>,
+    field_metrics: String,
+    gap_open: Option<f64>,
+    substitution: String,
+    /// One-based index of the run within an experiment.
+    run: Option<usize>,
+}
+
+/// Mean of a score over all linkage attacks of a run, with its standard deviation and bootstrap confidence interval.
+#[derive(Debug, Clone, Copy)]
+pub struct Score {
+    pub mean: f64,
+    pub std: f64,
+    pub ci_low: f64,
+    pub ci_high: f64,
+}
+
+impl Score {
+    fn from_list<R: Rng>(data: &[f64], rng: &mut R, config: &Config) -> Score {
+        let (ci_low, ci_high) =
+            stats::bootstrap_ci(data, rng, config.bootstrap_samples, config.confidence_level);
+        Score {
+            mean: mean(data),
+            std: std_deviation(data),
+            ci_low,
+            ci_high,
+        }
+    }
+}
+
+impl Display for Score {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} [{}, {}]", self.mean, self.ci_low, self.ci_high)
+    }
+}
+
+/// Aggregated performance scores of all linkage attacks of a run.
+#[derive(Debug)]
+pub struct RankSummary {
+    pub top_1: Score,
+    pub top_10: Score,
+    pub top_10_percent: Score,
+    /// Mean reciprocal rank of the target user.
+    pub mrr: Score,
+    pub median_rank: f64,
+    /// Share of attacks in which the target user is ranked within the top k, for every configured k.
+    pub top_k: Vec<(usize, Score)>,
+    /// Cumulative match characteristic: the share of attacks in which the target user is ranked within
+    /// the top r, for r = 1, 2, ... up to the first rank at which all target users are found.
+    pub cmc: Vec<f64>,
+}
+
+impl RankSummary {
+    /// Summarizes the results. The confidence intervals are bootstrapped with a generator seeded by the
+    /// configured seed, such that they are reproducible. Returns None without results, as none of the
+    /// scores is defined then.
+    pub fn from_results(result_list: &[AttackResult], config: &Config) -> Option<RankSummary> {
+        if result_list.is_empty() {
+            return None;
+        }
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        let share_list = |is_hit: &dyn Fn(&AttackResult) -> bool| -> Vec<f64> {
+            stats::to_share_list(result_list.iter().map(is_hit))
+        };
+        let top_1_list = share_list(&|result| result.is_in_top_k(1));
+        let top_10_list = share_list(&|result| result.is_in_top_k(10));
+        let top_10_percent_list = share_list(&|result| result.is_in_top_10_percent());
+
+        let rank_list: Vec<f64> = result_list.iter().map(|result| result.rank as f64).collect();
+        let reciprocal_rank_list: Vec<f64> = rank_list.iter().map(|rank| 1.0 / rank).collect();
+
+        let max_rank = result_list.iter().map(|result| result.rank).max().unwrap_or_default();
+        let mut num_hits_at_rank: Vec<usize> = vec![0; max_rank];
+        for result in result_list.iter() {
+            num_hits_at_rank[result.rank - 1] += 1;
+        }
+        let cmc: Vec<f64> = num_hits_at_rank
+            .iter()
+            .scan(0, |num_hits, num_hits_at| {
+                *num_hits += num_hits_at;
+                Some(*num_hits as f64 / result_list.len() as f64)
+            })
+            .collect();
+
+        Some(RankSummary {
+            top_1: Score::from_list(&top_1_list, &mut rng, config),
+            top_10: Score::from_list(&top_10_list, &mut rng, config),
+            top_10_percent: Score::from_list(&top_10_percent_list, &mut rng, config),
+            mrr: Score::from_list(&reciprocal_rank_list, &mut rng, config),
+            median_rank: median(&rank_list),
+            top_k: config
+                .top_k
+                .iter()
+                .map(|k| {
+                    let top_k_list = share_list(&|result| result.is_in_top_k(*k));
+                    (*k, Score::from_list(&top_k_list, &mut rng, config))
+                })
+                .collect(),
+            cmc,
+        })
+    }
+}
+
+/// Summarizes the results of all linkage attacks of a run, logs the scores and writes them to file.
+/// The field weights of every fold are averaged and reported with the scores.
+pub fn evaluate(
+    config: &Config,
+    user_id_map: &UserIdMap,
+    user_result_list: &[UserResult],
+    field_weights_list: &[FieldWeights],
+) {
+    let result_list: Vec<AttackResult> = user_result_list
+        .iter()
+        .flat_map(|(_, result_list)| result_list.iter().cloned())
+        .collect();
+    // The ranks are only meaningful for target users that are among the candidates
+    let seen_result_list: Vec<AttackResult> =
+        result_list.iter().filter(|result| result.seen).cloned().collect();
+    let summary = RankSummary::from_results(&seen_result_list, config);
+    let higher_is_better = config.approach.higher_is_better();
+    let open_world_point =
+        open_world::evaluate_threshold(&result_list, config.match_threshold, higher_is_better);
+
+    if let Some(summary) = &summary {
+        for (k, top_k) in summary.top_k.iter() {
+            log::info!("Top {}: {}", k, top_k);
+        }
+        log::info!("Top 10 Percent: {}", summary.top_10_percent);
+        log::info!("Mean Reciprocal Rank: {}", summary.mrr);
+        log::info!("Median Rank: {:?}", summary.median_rank);
+    }
+    let num_linked = result_list.iter().filter(|result| result.linked_user.is_some()).count();
+    let num_wrongly_linked = user_result_list
+        .iter()
+        .flat_map(|(user_target, result_list)| {
+            result_list
+                .iter()
+                .filter(move |result| result.linked_user.is_some_and(|user| user != *user_target))
+        })
+        .count();
+    if config.dependent {
+        log::info!(
+            "Linked {:?} target traces, {:?} of them to the history of another user",
+            num_linked,
+            num_wrongly_linked
+        );
+    }
+    if config.unseen_fraction > 0.0 || config.match_threshold.is_some() {
+        log::info!(
+            "Precision: {:?}, Recall: {:?}, False Match Rate: {:?}",
+            open_world_point.precision,
+            open_world_point.recall,
+            open_world_point.false_match_rate
+        );
+        let curve = open_world::gen_curve(&result_list, higher_is_better);
+        open_world::write_curve(&config.run_path(open_world::CURVE_PATH), &curve)
+            .expect("Error writing the open-world curve.");
+    }
+    if seen_result_list.iter().any(|result| result.gap_days.is_some()) {
+        let bucket_list = decay::gen_buckets(&seen_result_list, &config.gap_buckets);
+        for bucket in bucket_list.iter() {
+            log::info!(
+                "Top 1 with a gap of {} to {} days: {} ({} attacks)",
+                bucket.gap_from,
+                bucket.gap_to,
+                bucket.top_1,
+                bucket.num_attacks
+            );
+        }
+        decay::write_buckets(&config.run_path(decay::GAP_EVAL_PATH), &bucket_list)
+            .expect("Error writing the gap evaluation.");
+    }
+
+    // Write metrics to final evaluation file
+    match &summary {
+        Some(summary) => write_to_file(
+            config,
+            summary,
+            &open_world_point,
+            (num_linked, num_wrongly_linked),
+            &FieldWeights::mean(field_weights_list),
+        )
+        .expect("Error writing to evaluation file."),
+        None => log::warn!("No linkage attack against a target user among the candidates, skipping the evaluation row"),
+    }
+    write_user_results(&config.run_path(USER_EVAL_PATH), user_id_map, user_result_list)
+        .expect("Error writing to user evaluation file.");
+    if let Some(path) = &config.attack_log {
+        let path = &config.run_path(path);
+        write_attack_log(path, user_id_map, user_result_list).expect("Error writing the attack log.");
+        log::info!("Stored the result of every linkage attack in {:?}", path);
+    }
+}
+
+/// Inserts the suffix between the file stem and the extension of the path, e.g. `map.fold2.pkl`.
+pub fn insert_suffix(path: &str, suffix: &str) -> String {
+    let path = Path::new(path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(extension) => format!("{}.{}.{}", stem, suffix, extension.to_string_lossy()),
+        None => format!("{}.{}", stem, suffix),
+    };
+    path.with_file_name(file_name).to_string_lossy().into_owned()
 }
 
 /// Writes the performance scores as well as the configuration to file.
 pub fn write_to_file(
     config: &Config,
-    top_1: f64,
-    top_1_std: f64,
-    top_10: f64,
-    top_10_std: f64,
-    top_10_percent: f64,
-    top_10_percent_std: f64,
+    summary: &RankSummary,
+    open_world_point: &OpenWorldPoint,
+    (num_linked, num_wrongly_linked): (usize, usize),
+    field_weights: &FieldWeights,
 ) -> Result<(), Box<dyn Error>> {
     let file = std::fs::OpenOptions::new()
-        .write(true)
         .create(true)
         .append(true)
         .open(EVAL_PATH)
@@ -154,11 +433,13 @@ pub fn write_to_file(
         .from_writer(file);
 
     wtr.serialize(Row {
+        session_strategy: config.session_strategy.to_string(),
         delay_limit: config.delay_limit,
+        window_size: config.window_size,
+        window_overlap: config.window_overlap,
         max_trace_len: config.max_trace_len,
         min_trace_len: config.min_trace_len,
         max_trace_duration: config.max_trace_duration,
-        max_rate: config.max_rate,
         min_num_traces: config.min_num_traces,
         path: config.path.to_string(),
         seed: config.seed,
@@ -172,14 +453,458 @@ pub fn write_to_file(
         multiple: config.multiple,
         metric: config.metric.to_string(),
         strategy: config.strategy.to_string(),
-        scoring_matrix: format!("{:?}", &config.scoring_matrix),
+        scoring_matrix: config.scoring_matrix.to_string(),
         scope: config.scope.to_string(),
-        top_1: top_1,
-        top_1_std: top_1_std,
-        top_10: top_10,
-        top_10_std: top_10_std,
-        top_10_percent: top_10_percent,
-        top_10_percent_std: top_10_percent_std,
+        top_1: summary.top_1.mean,
+        top_1_std: summary.top_1.std,
+        top_10: summary.top_10.mean,
+        top_10_std: summary.top_10.std,
+        top_10_percent: summary.top_10_percent.mean,
+        top_10_percent_std: summary.top_10_percent.std,
+        mrr: summary.mrr.mean,
+        median_rank: summary.median_rank,
+        top_k: summary
+            .top_k
+            .iter()
+            .map(|(k, top_k)| format!("{}:{}:{}:{}", k, top_k.mean, top_k.ci_low, top_k.ci_high))
+            .collect::<Vec<String>>()
+            .join(" "),
+        cmc: summary
+            .cmc
+            .iter()
+            .map(f64::to_string)
+            .collect::<Vec<String>>()
+            .join(" "),
+        top_1_ci_low: summary.top_1.ci_low,
+        top_1_ci_high: summary.top_1.ci_high,
+        top_10_ci_low: summary.top_10.ci_low,
+        top_10_ci_high: summary.top_10.ci_high,
+        top_10_percent_ci_low: summary.top_10_percent.ci_low,
+        top_10_percent_ci_high: summary.top_10_percent.ci_high,
+        mrr_ci_low: summary.mrr.ci_low,
+        mrr_ci_high: summary.mrr.ci_high,
+        unseen_fraction: config.unseen_fraction,
+        match_threshold: config.match_threshold,
+        precision: open_world_point.precision,
+        recall: open_world_point.recall,
+        false_match_rate: open_world_point.false_match_rate,
+        acceptance: config.acceptance.to_string(),
+        acceptance_threshold: config.acceptance_threshold,
+        num_linked,
+        num_wrongly_linked,
+        split_strategy: config.split_strategy.to_string(),
+        split_ratio: config.split_ratio,
+        split_cutoff: config.split_cutoff,
+        num_folds: split::from_config(config).num_folds(),
+        weighting: config.weighting.to_string(),
+        field_weights: field_weights.format(&config.fields),
+        validation_top_1: field_weights.validation_top_1,
+        field_metrics: config
+            .fields
+            .iter()
+            .zip(config.field_metrics.iter())
+            .map(|(field, metric)| format!("{}:{}", field, metric))
+            .collect::<Vec<String>>()
+            .join(" "),
+        gap_open: config.gap_open,
+        substitution: config.substitution.to_string(),
+        run: config.run.map(|run| run + 1),
     })?;
     Ok(())
 }
+
+#[derive(Serialize)]
+struct UserRow<'a> {
+    user_id: u32,
+    original_user_id: &'a str,
+    num_attacks: usize,
+    top_1: f64,
+    top_10: f64,
+    top_10_percent: f64,
+    mrr: f64,
+}
+
+/// Writes the share of successful linkage attacks per target user to the given file.
+///
+/// Each user is identified by the internal as well as the original id, such that the results
+/// can be joined with external data about the users.
+pub fn write_user_results(
+    path: &str,
+    user_id_map: &UserIdMap,
+    user_result_list: &[UserResult],
+) -> Result<(), Box<dyn Error>> {
+    let mut wtr = WriterBuilder::new()
+        .delimiter(b',')
+        .has_headers(true)
+        .from_path(path)?;
+
+    let mut user_result_list: Vec<&UserResult> = user_result_list.iter().collect();
+    user_result_list.sort_unstable_by_key(|(user_id, _)| *user_id);
+    for (user_id, result_list) in user_result_list.into_iter() {
+        let share = |is_hit: fn(&AttackResult) -> bool| {
+            let num_hits = result_list.iter().filter(|result| is_hit(result)).count();
+            num_hits as f64 / result_list.len() as f64
+        };
+        wtr.serialize(UserRow {
+            user_id: *user_id,
+            original_user_id: user_id_map.original(*user_id),
+            num_attacks: result_list.len(),
+            top_1: share(|result| result.is_in_top_k(1)),
+            top_10: share(|result| result.is_in_top_k(10)),
+            top_10_percent: share(|result| result.is_in_top_10_percent()),
+            mrr: result_list
+                .iter()
+                .map(|result| 1.0 / result.rank as f64)
+                .sum::<f64>()
+                / result_list.len() as f64,
+        })?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct Candidate<'a> {
+    user_id: u32,
+    original_user_id: &'a str,
+    score: f64,
+}
+
+#[derive(Serialize)]
+struct AttackRow<'a, C> {
+    user_id: u32,
+    original_user_id: &'a str,
+    target_idx: Option<usize>,
+    rank: usize,
+    num_candidates: usize,
+    seen: bool,
+    accepted: Option<bool>,
+    linked_user_id: Option<u32>,
+    gap_days: Option<f64>,
+    candidates: C,
+}
+
+impl<'a> AttackRow<'a, Vec<Candidate<'a>>> {
+    fn new(user_id: u32, result: &AttackResult, user_id_map: &'a UserIdMap) -> Self {
+        AttackRow {
+            user_id,
+            original_user_id: user_id_map.original(user_id),
+            target_idx: result.target_idx,
+            rank: result.rank,
+            num_candidates: result.num_candidates,
+            seen: result.seen,
+            accepted: result.accepted,
+            linked_user_id: result.linked_user,
+            gap_days: result.gap_days,
+            candidates: result
+                .candidates
+                .iter()
+                .map(|(candidate, score)| Candidate {
+                    user_id: *candidate,
+                    original_user_id: user_id_map.original(*candidate),
+                    score: *score,
+                })
+                .collect(),
+        }
+    }
+
+    /// Lists the candidates as `original_user_id:score`, separated by spaces, to fit into a CSV cell.
+    fn flatten(self) -> AttackRow<'a, String> {
+        AttackRow {
+            user_id: self.user_id,
+            original_user_id: self.original_user_id,
+            target_idx: self.target_idx,
+            rank: self.rank,
+            num_candidates: self.num_candidates,
+            seen: self.seen,
+            accepted: self.accepted,
+            linked_user_id: self.linked_user_id,
+            gap_days: self.gap_days,
+            candidates: self
+                .candidates
+                .iter()
+                .map(|candidate| format!("{}:{}", candidate.original_user_id, candidate.score))
+                .collect::<Vec<String>>()
+                .join(" "),
+        }
+    }
+}
+
+/// Writes one record per linkage attack to file, including the best matching candidates and their
+/// scores (alignment scores or distances, depending on the approach).
+///
+/// Files ending with `.json` or `.jsonl` are written as JSON Lines, all others as CSV.
+pub fn write_attack_log(
+    path: &str,
+    user_id_map: &UserIdMap,
+    user_result_list: &[UserResult],
+) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = Path::new(path).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut user_result_list: Vec<&UserResult> = user_result_list.iter().collect();
+    user_result_list.sort_unstable_by_key(|(user_id, _)| *user_id);
+    let rows = user_result_list.into_iter().flat_map(|(user_id, result_list)| {
+        result_list
+            .iter()
+            .map(move |result| AttackRow::new(*user_id, result, user_id_map))
+    });
+
+    if is_json_lines(path) {
+        let mut writer = BufWriter::new(fs::File::create(path)?);
+        for row in rows {
+            serde_json::to_writer(&mut writer, &row)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+    } else {
+        let mut wtr = WriterBuilder::new()
+            .delimiter(b',')
+            .has_headers(true)
+            .from_path(path)?;
+        for row in rows {
+            wtr.serialize(row.flatten())?;
+        }
+        wtr.flush()?;
+    }
+    Ok(())
+}
+
+/// A linkage attack read back from an attack log, identified by the original id of the target user and the target trace.
+#[derive(Debug, Deserialize)]
+pub struct LoggedAttack {
+    pub original_user_id: String,
+    pub target_idx: Option<usize>,
+    pub rank: usize,
+}
+
+/// Reads the linkage attacks of an attack log written by `write_attack_log`.
+pub fn read_attack_log(path: &str) -> Result<Vec<LoggedAttack>, Box<dyn Error>> {
+    if is_json_lines(path) {
+        let reader = BufReader::new(fs::File::open(path)?);
+        let mut attacks: Vec<LoggedAttack> = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                attacks.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(attacks)
+    } else {
+        let mut rdr = csv::Reader::from_path(path)?;
+        let attacks = rdr.deserialize().collect::<Result<Vec<LoggedAttack>, csv::Error>>()?;
+        Ok(attacks)
+    }
+}
+
+fn is_json_lines(path: &str) -> bool {
+    Path::new(path).extension().is_some_and(|extension| {
+        extension.eq_ignore_ascii_case("json") || extension.eq_ignore_ascii_case("jsonl")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli;
+
+    const EPS: f64 = 1e-12;
+
+    fn result(rank: usize, num_candidates: usize) -> AttackResult {
+        AttackResult {
+            target_idx: Some(0),
+            rank,
+            num_candidates,
+            seen: rank <= num_candidates,
+            best_score: Some(1.0),
+            candidates: Vec::new(),
+            accepted: None,
+            linked_user: None,
+            gap_days: None,
+        }
+    }
+
+    fn tuples(scores: &[(u32, f64)]) -> Vec<(u32, OrderedFloat<f64>)> {
+        scores.iter().map(|(user, score)| (*user, OrderedFloat(*score))).collect()
+    }
+
+    #[test]
+    fn most_frequent_element_breaks_ties_by_the_smallest_value() {
+        assert_eq!(get_most_freq_element(&[3, 1, 3, 2]), 3);
+        assert_eq!(get_most_freq_element(&[5, 2, 9, 2, 5, 9]), 2);
+        assert_eq!(get_most_freq_element(&["m", "f", "d"]), "d");
+        // Many distinct values make a tie broken by the hash map order unlikely to pass by chance
+        let values: Vec<u32> = (0..200).rev().collect();
+        for _ in 0..10 {
+            assert_eq!(get_most_freq_element(&values), 0);
+        }
+    }
+
+    #[test]
+    fn rank_is_the_first_position_of_the_target_user() {
+        let tuples = tuples(&[(3, 5.0), (1, 4.0), (3, 3.0), (2, 2.0), (1, 1.0)]);
+        let result = AttackResult::from_sorted_tuples(&2, Some(4), &tuples, 2);
+        assert_eq!(result.rank, 3);
+        assert_eq!(result.num_candidates, 3);
+        assert!(result.seen);
+        assert_eq!(result.target_idx, Some(4));
+        assert_eq!(result.best_score, Some(5.0));
+        // Candidates are logged once, with their best score
+        assert_eq!(result.candidates, vec![(3, 5.0), (1, 4.0)]);
+        assert_eq!(AttackResult::from_sorted_tuples(&1, None, &tuples, 0).rank, 2);
+        assert!(AttackResult::from_sorted_tuples(&1, None, &tuples, 0).candidates.is_empty());
+    }
+
+    #[test]
+    fn missing_target_user_is_ranked_last() {
+        let result = AttackResult::from_sorted_tuples(&9, None, &tuples(&[(1, 0.9), (2, 0.8), (1, 0.7)]), 10);
+        assert_eq!(result.rank, 3);
+        assert_eq!(result.num_candidates, 2);
+        assert!(!result.seen);
+        assert!(!result.is_in_top_k(2));
+
+        let result = AttackResult::from_sorted_tuples(&9, None, &[], 10);
+        assert_eq!((result.rank, result.num_candidates, result.best_score), (1, 0, None));
+        assert!(!result.seen);
+    }
+
+    #[test]
+    fn summary_matches_known_values() {
+        let config = cli::test_config(&[]);
+        let result_list: Vec<AttackResult> = [1, 2, 1, 5, 3].iter().map(|rank| result(*rank, 10)).collect();
+        let summary = RankSummary::from_results(&result_list, &config).unwrap();
+        assert!((summary.top_1.mean - 0.4).abs() < EPS);
+        assert!((summary.top_10.mean - 1.0).abs() < EPS);
+        assert!((summary.top_10_percent.mean - 0.4).abs() < EPS);
+        assert!((summary.mrr.mean - (1.0 + 0.5 + 1.0 + 0.2 + 1.0 / 3.0) / 5.0).abs() < EPS);
+        assert_eq!(summary.median_rank, 2.0);
+        let top_k: Vec<(usize, f64)> = summary.top_k.iter().map(|(k, score)| (*k, score.mean)).collect();
+        assert_eq!(top_k, vec![(1, 0.4), (5, 1.0), (10, 1.0)]);
+        assert_eq!(summary.cmc, vec![0.4, 0.6, 0.8, 0.8, 1.0]);
+    }
+
+    #[test]
+    fn cmc_is_monotonic_and_reaches_one() {
+        let config = cli::test_config(&[]);
+        let result_list: Vec<AttackResult> = (0..50).map(|i| result(1 + (i * 7) % 13, 20)).collect();
+        let summary = RankSummary::from_results(&result_list, &config).unwrap();
+        assert_eq!(summary.cmc.len(), 13);
+        assert!(summary.cmc.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!((summary.cmc.last().unwrap() - 1.0).abs() < EPS);
+        assert!((summary.cmc[0] - summary.top_1.mean).abs() < EPS);
+    }
+
+    #[test]
+    fn summary_counts_missing_targets_as_misses() {
+        let config = cli::test_config(&[]);
+        let tuples = tuples(&[(1, 0.9), (2, 0.8)]);
+        let result_list = vec![
+            AttackResult::from_sorted_tuples(&1, None, &tuples, 0),
+            AttackResult::from_sorted_tuples(&9, None, &tuples, 0),
+        ];
+        let summary = RankSummary::from_results(&result_list, &config).unwrap();
+        assert!((summary.top_1.mean - 0.5).abs() < EPS);
+        assert!((summary.mrr.mean - (1.0 + 1.0 / 3.0) / 2.0).abs() < EPS);
+        assert_eq!(summary.cmc, vec![0.5, 0.5, 1.0]);
+    }
+
+    /// The columns of an attack log row that `read_attack_log` does not read back.
+    #[derive(Debug, Deserialize)]
+    struct LoggedRow {
+        user_id: u32,
+        accepted: Option<bool>,
+        linked_user_id: Option<u32>,
+        candidates: String,
+    }
+
+    fn gen_user_result_list() -> (UserIdMap, Vec<UserResult>) {
+        let mut user_id_map = UserIdMap::default();
+        for original_id in ["alice", "bob", "carol"] {
+            user_id_map.insert(original_id);
+        }
+        let tuples = tuples(&[(2, 0.5), (0, 0.25), (2, 0.125), (1, -1.5)]);
+        let mut linked = AttackResult::from_sorted_tuples(&0, Some(3), &tuples, 2);
+        linked.accepted = Some(true);
+        linked.linked_user = Some(2);
+        let mut rejected = AttackResult::from_sorted_tuples(&0, Some(1), &tuples, 2);
+        rejected.accepted = Some(false);
+        let combined = AttackResult::from_sorted_tuples(&1, None, &tuples, 2);
+        (user_id_map, vec![(1, vec![combined]), (0, vec![linked, rejected])])
+    }
+
+    #[test]
+    fn attack_log_survives_a_round_trip() {
+        let (user_id_map, user_result_list) = gen_user_result_list();
+        let dir = std::env::temp_dir().join(format!("attack_log_{}", std::process::id()));
+        for file_name in ["attacks.csv", "attacks.jsonl", "attacks.json"] {
+            let path = dir.join(file_name);
+            let path = path.to_str().unwrap();
+            write_attack_log(path, &user_id_map, &user_result_list).unwrap();
+
+            // The attacks are ordered by the internal id of the target user
+            let attacks: Vec<(String, Option<usize>, usize)> = read_attack_log(path)
+                .unwrap()
+                .into_iter()
+                .map(|attack| (attack.original_user_id, attack.target_idx, attack.rank))
+                .collect();
+            assert_eq!(
+                attacks,
+                vec![("alice".into(), Some(3), 2), ("alice".into(), Some(1), 2), ("bob".into(), None, 3)],
+                "{}",
+                file_name
+            );
+
+            let rows: Vec<(u32, Option<bool>, Option<u32>, String)> = if is_json_lines(path) {
+                fs::read_to_string(path)
+                    .unwrap()
+                    .lines()
+                    .map(|line| {
+                        let row: serde_json::Value = serde_json::from_str(line).unwrap();
+                        let candidates = row["candidates"]
+                            .as_array()
+                            .unwrap()
+                            .iter()
+                            .map(|candidate| {
+                                format!("{}:{}", candidate["original_user_id"].as_str().unwrap(), candidate["score"])
+                            })
+                            .collect::<Vec<String>>()
+                            .join(" ");
+                        (
+                            row["user_id"].as_u64().unwrap() as u32,
+                            row["accepted"].as_bool(),
+                            row["linked_user_id"].as_u64().map(|user| user as u32),
+                            candidates,
+                        )
+                    })
+                    .collect()
+            } else {
+                csv::Reader::from_path(path)
+                    .unwrap()
+                    .deserialize()
+                    .map(|row| {
+                        let row: LoggedRow = row.unwrap();
+                        (row.user_id, row.accepted, row.linked_user_id, row.candidates)
+                    })
+                    .collect()
+            };
+            // Only the best two candidates are logged, each once with its best score
+            let candidates = "carol:0.5 alice:0.25".to_string();
+            assert_eq!(
+                rows,
+                vec![
+                    (0, Some(true), Some(2), candidates.clone()),
+                    (0, Some(false), None, candidates.clone()),
+                    (1, None, None, candidates),
+                ],
+                "{}",
+                file_name
+            );
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn summary_of_no_results_is_none() {
+        assert!(RankSummary::from_results(&[], &cli::test_config(&[])).is_none());
+    }
+}